@@ -0,0 +1,84 @@
+//! Async wrappers running blocking mapping operations on Tokio's blocking thread-pool
+//!
+//! `mmap()` (especially with `MAP_POPULATE`) and `msync()` can block for a long time, so these are run with `tokio::task::spawn_blocking()` instead of on the runtime's worker threads.
+use super::*;
+use std::path::Path;
+
+/// Run `f` on the blocking thread-pool, and wait for its result.
+///
+/// # Panics
+/// If `f` panics, the panic is resumed on the caller. If the runtime is shutting down, the task may be cancelled before it runs, which also panics.
+async fn blocking<F, R>(f: F) -> R
+where F: FnOnce() -> R + Send + 'static,
+      R: Send + 'static
+{
+    match tokio::task::spawn_blocking(f).await {
+	Ok(r) => r,
+	Err(join) if join.is_panic() => std::panic::resume_unwind(join.into_panic()),
+	Err(join) => panic!("Blocking task failed: {join}"),
+    }
+}
+
+impl MappedFile<std::fs::File>
+{
+    /// Open the file at `path`, and map the whole of it with memory protection as provided by `perm`, and mapping flags provided by `flags`.
+    ///
+    /// The file is opened with the access `perm` and `flags` need (e.g. read-write for a writable `Flags::Shared` mapping.) Opening and mapping are both run on Tokio's blocking thread-pool.
+    ///
+    /// # Returns
+    /// If opening the file fails, or `fstat()` or `mmap()` fails (see `from_file()`), then the error is returned.
+    ///
+    /// # Panics
+    /// If the runtime is shutting down before the blocking task runs.
+    pub async fn open_async(path: impl AsRef<Path>, perm: Perm, flags: Flags) -> io::Result<Self>
+    {
+	let path = path.as_ref().to_owned();
+	blocking(move || {
+	    let (read, write) = perm.open_rw(flags);
+	    let file = std::fs::OpenOptions::new().read(read).write(write).open(path)?;
+	    Self::from_file(file, perm, flags)
+	}).await
+    }
+}
+
+impl<T: AsRawFd + Send + 'static> MappedFile<T>
+{
+    /// Flush the mapping with `flush()` on Tokio's blocking thread-pool, used as an async alternative to `try_flushed_range(.., flush)`.
+    ///
+    /// The mapping is moved onto the blocking thread for the call, so it cannot be unmapped while `msync()` is running, and is returned afterwards.
+    ///
+    /// # Returns
+    /// If `msync()` fails, then the `io::Error` along with the mapping is returned.
+    ///
+    /// # Panics
+    /// If the runtime is shutting down before the blocking task runs (the mapping is then dropped.)
+    #[inline]
+    pub async fn flush_async(self, flush: Flush) -> Result<Self, TryNewError<Self>>
+    {
+	blocking(move || self.try_flushed_range(.., flush)).await
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use file::memory::MemoryFile;
+
+    #[test]
+    fn open_and_flush()
+    {
+	let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+	let file = MemoryFile::with_size(get_page_size()).unwrap();
+	let path = file.proc_path();
+	rt.block_on(async move {
+	    let mut map = MappedFile::open_async(&path, Perm::ReadWrite, Flags::Shared).await.unwrap();
+	    assert_eq!(map.len(), get_page_size());
+	    map[0] = 0xff;
+	    let map = map.flush_async(Flush::Wait).await.unwrap();
+	    assert_eq!(map[0], 0xff);
+	});
+	let check = MappedFile::new(file, get_page_size(), Perm::Readonly, Flags::Shared).unwrap();
+	assert_eq!(check[0], 0xff);
+    }
+}
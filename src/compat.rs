@@ -0,0 +1,216 @@
+//! Wrappers following the names of `memmap2`'s `Mmap` and `MmapMut`, to ease migrating from it
+//!
+//! Both map a `dup()`'d alias of the given file, shared, over the whole of its length. `memmap2` code can usually switch by changing its imports:
+//!
+//! ```
+//! use mapped_file::compat::MmapMut;
+//! # fn main() -> std::io::Result<()> {
+//! # let file = std::fs::File::from(mapped_file::file::memory::MemoryFile::with_size(4096)?);
+//! let mut map = unsafe { MmapMut::map_mut(&file)? };
+//! map[0] = 1;
+//! map.flush()?;
+//! let map = map.make_read_only()?;
+//! assert_eq!(map[0], 1);
+//! # Ok(()) }
+//! ```
+//!
+//! Unlike `memmap2`, the mappings are `MappedFile`s underneath, and `into_inner()` returns it for using the rest of this crate's API.
+use super::*;
+use file::ManagedFD;
+
+/// Map the whole of `file`, shared, with `perm`.
+fn map_whole(file: &(impl AsRawFd + ?Sized), perm: Perm) -> io::Result<MappedFile<ManagedFD>>
+{
+    let file = ManagedFD::alias(file)?;
+    let mut stat = mem::MaybeUninit::uninit();
+    if unsafe { libc::fstat(file.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+	return Err(io::Error::last_os_error());
+    }
+    let len = usize::try_from(unsafe { stat.assume_init() }.st_size).map_err(|_| io::Error::new(io::ErrorKind::Unsupported, "File size exceeds pointer word width"))?;
+    if len == 0 {
+	return Ok(MappedFile {
+	    file,
+	    map: MappedSlice::empty(),
+	});
+    }
+    MappedFile::new(file, len, perm, Flags::Shared)
+}
+
+/// A read-only, shared mapping of a whole file. Equivalent to `memmap2::Mmap`.
+#[derive(Debug)]
+pub struct Mmap(MappedFile<ManagedFD>);
+
+/// A writable, shared mapping of a whole file. Equivalent to `memmap2::MmapMut`.
+#[derive(Debug)]
+pub struct MmapMut(MappedFile<ManagedFD>);
+
+impl Mmap
+{
+    /// Map the whole of `file` read-only.
+    ///
+    /// # Safety
+    /// As with `memmap2`, the file **must not** be modified (or truncated) by anything else while it is mapped, as this would change memory behind a shared reference.
+    ///
+    /// # Returns
+    /// If duplicating the file descriptor, `fstat()`, or `mmap()` fails, then the error is returned.
+    #[inline]
+    pub unsafe fn map(file: &(impl AsRawFd + ?Sized)) -> io::Result<Self>
+    {
+	map_whole(file, Perm::Readonly).map(Self)
+    }
+
+    /// Make the mapping writable via `protect()`.
+    ///
+    /// # Returns
+    /// If `mprotect()` fails (e.g. `EACCES` if the file was not opened for writing), then the error is returned and the mapping is dropped.
+    #[inline]
+    pub fn make_mut(mut self) -> io::Result<MmapMut>
+    {
+	self.0.protect(Perm::ReadWrite)?;
+	Ok(MmapMut(self.0))
+    }
+
+    /// The underlying mapping.
+    #[inline]
+    pub fn into_inner(self) -> MappedFile<ManagedFD>
+    {
+	self.0
+    }
+}
+
+impl MmapMut
+{
+    /// Map the whole of `file` read-write. The file must have been opened for reading and writing.
+    ///
+    /// # Safety
+    /// As with `memmap2`, the file **must not** be modified (or truncated) by anything else while it is mapped.
+    ///
+    /// # Returns
+    /// If duplicating the file descriptor, `fstat()`, or `mmap()` fails, then the error is returned.
+    #[inline]
+    pub unsafe fn map_mut(file: &(impl AsRawFd + ?Sized)) -> io::Result<Self>
+    {
+	map_whole(file, Perm::ReadWrite).map(Self)
+    }
+
+    /// Sync the whole mapping to the file, waiting for the write-back to complete.
+    #[inline]
+    pub fn flush(&self) -> io::Result<()>
+    {
+	let (addr, len) = self.0.raw_parts();
+	MappedFile::<ManagedFD>::msync_raw(addr, len, Flush::Wait)
+    }
+
+    /// Start syncing the whole mapping to the file, without waiting for the write-back to complete.
+    #[inline]
+    pub fn flush_async(&self) -> io::Result<()>
+    {
+	let (addr, len) = self.0.raw_parts();
+	MappedFile::<ManagedFD>::msync_raw(addr, len, Flush::Async)
+    }
+
+    /// Sync `len` bytes of the mapping from `offset` to the file, waiting for the write-back to complete.
+    ///
+    /// # Panics
+    /// If the range is out of bounds of the mapping.
+    #[inline]
+    pub fn flush_range(&self, offset: usize, len: usize) -> io::Result<()>
+    {
+	let (addr, len) = self.0.raw_page_range(offset..offset + len);
+	MappedFile::<ManagedFD>::msync_raw(addr, len, Flush::Wait)
+    }
+
+    /// Make the mapping read-only via `protect()`.
+    ///
+    /// # Returns
+    /// If `mprotect()` fails, then the error is returned and the mapping is dropped.
+    #[inline]
+    pub fn make_read_only(mut self) -> io::Result<Mmap>
+    {
+	self.0.protect(Perm::Readonly)?;
+	Ok(Mmap(self.0))
+    }
+
+    /// The underlying mapping.
+    #[inline]
+    pub fn into_inner(self) -> MappedFile<ManagedFD>
+    {
+	self.0
+    }
+}
+
+impl ops::Deref for Mmap
+{
+    type Target = [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target
+    {
+	&self.0
+    }
+}
+
+impl AsRef<[u8]> for Mmap
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8]
+    {
+	&self.0
+    }
+}
+
+impl ops::Deref for MmapMut
+{
+    type Target = [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target
+    {
+	&self.0
+    }
+}
+
+impl ops::DerefMut for MmapMut
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target
+    {
+	&mut self.0
+    }
+}
+
+impl AsRef<[u8]> for MmapMut
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8]
+    {
+	&self.0
+    }
+}
+
+impl AsMut<[u8]> for MmapMut
+{
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8]
+    {
+	&mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use file::memory::MemoryFile;
+
+    #[test]
+    fn flush_empty_file()
+    {
+	let file = MemoryFile::new().unwrap();
+	let map = unsafe { MmapMut::map_mut(&file) }.unwrap();
+	assert!(map.is_empty());
+	map.flush().unwrap();
+	map.flush_async().unwrap();
+	map.flush_range(0, 0).unwrap();
+	let map = map.make_read_only().unwrap();
+	assert!(map.is_empty());
+    }
+}
@@ -27,23 +27,44 @@ macro_rules! opaque {
 }
 pub(crate) use opaque;
 
-/// Construct an ad-hoc error wrapping the last OS error.
+/// Construct an ad-hoc error wrapping an OS error.
+///
+/// * `os_error!("fmt", args...)` wraps the last OS error (`errno`.)
+/// * `os_error!(raw = code; "fmt", args...)` wraps the error number `code`, for calls that return it directly instead of setting `errno` (e.g. the `posix_*()` family.)
+/// * `os_error!(struct Name => "message")` defines a named error type wrapping the last OS error.
+///
+/// # Example
+/// ```
+/// # use mapped_file::os_error;
+/// // A fabricated error number, as if returned by e.g. `posix_fadvise()`.
+/// let code = 22; // EINVAL
+/// let error = std::io::Error::from(os_error!(raw = code; "posix_fadvise() on fd {} failed", 3));
+/// assert_eq!(error.raw_os_error(), Some(code));
+/// assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+/// ```
+#[macro_export]
 macro_rules! os_error {
-    ($fmt:literal $(, $args:expr)*) => {
+    (@adhoc $ctor:ident($($pre:expr)?) $fmt:literal $(, $args:expr)*) => {
 	{
 	    #[derive(Debug)]
             struct AdHoc<'a>(::std::fmt::Arguments<'a>);
 	    impl<'a> ::std::fmt::Display for AdHoc<'a>
 	    {
 		#[inline] 
-		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+		fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result
 		{
-		    write!(f, "{}", self.0)
+		    ::std::write!(f, "{}", self.0)
 		}
 	    }
-	    $crate::err::WrappedOSError::last_os_error(AdHoc(::std::format_args!($fmt $(, $args)*)))
+	    $crate::err::WrappedOSError::$ctor($($pre,)? AdHoc(::std::format_args!($fmt $(, $args)*)))
 	}
     };
+    (raw = $code:expr; $fmt:literal $(, $args:expr)*) => {
+	$crate::os_error!(@adhoc from_os_error($code) $fmt $(, $args)*)
+    };
+    ($fmt:literal $(, $args:expr)*) => {
+	$crate::os_error!(@adhoc last_os_error() $fmt $(, $args)*)
+    };
     ($(#[$outer:meta])* $vis:vis struct $name:ident => $fmt:literal $(; $($rest:tt)*)?) => {
 
 	$(#[$outer])*
@@ -54,7 +75,7 @@ macro_rules! os_error {
 	impl ::std::fmt::Display for $name
 	{
 	    #[inline] 
-	    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> fmt::Result
+	    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result
 	    {
 		f.write_str($fmt)
 	    }
@@ -104,7 +125,6 @@ macro_rules! os_error {
     };
     () => {};
 }
-pub(crate) use os_error;
 
 const _: () = {
     os_error!(struct Test => "Test error");
@@ -112,6 +132,7 @@ const _: () = {
     fn r<E: ?Sized + error::Error>(_: &E) {}
     fn test() {
 	r(&os_error!("Some error message"));
+	r(&os_error!(raw = libc::EINVAL; "Some error message {}", 1));
     }
     t::<Test>()
 };
@@ -121,12 +142,14 @@ pub struct WrappedOSError<E: ?Sized>(io::Error, E);
 
 impl<E: fmt::Debug> WrappedOSError<E>
 {
-    pub(crate) fn last_os_error(ctx: E) -> Self
+    /// Wrap the last OS error (`errno`) with context `ctx`.
+    pub fn last_os_error(ctx: E) -> Self
     {
 	Self(io::Error::last_os_error(), ctx)
     }
 
-    pub(crate) fn from_os_error(raw: c_int, ctx: E) -> Self
+    /// Wrap the OS error number `raw` with context `ctx`.
+    pub fn from_os_error(raw: c_int, ctx: E) -> Self
     {
 	Self(io::Error::from_raw_os_error(raw), ctx)
     }
@@ -171,22 +194,94 @@ where WrappedOSError<E>: fmt::Display + fmt::Debug
 }
 
 impl<E: ?Sized> fmt::Display for WrappedOSError<E>
-where E: fmt::Debug
+where E: fmt::Display
 {
     #[inline] 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
     {
-	write!(f, "{:?}", &self.1)
+	write!(f, "{}", &self.1)
     }
 }
 impl<E: ?Sized> fmt::Debug for WrappedOSError<E>
-where E: fmt::Display
+where E: fmt::Debug
 {
     #[inline] 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
     {
-	write!(f, "{}", &self.1)
+	f.debug_struct("WrappedOSError")
+	    .field("context", &&self.1)
+	    .field("error", &self.0)
+	    .finish()
     }
 }
 
+/// A chain of context over an OS error: the outer context `C`, added over the inner context `E`.
+///
+/// See `WrappedOSError::with_context()`.
+pub struct Chain<C, E: ?Sized>(C, E);
 
+impl<C, E: ?Sized> Chain<C, E>
+{
+    /// The outer (most recently added) context.
+    #[inline]
+    pub fn outer(&self) -> &C
+    {
+	&self.0
+    }
+    /// The inner context.
+    #[inline]
+    pub fn inner(&self) -> &E
+    {
+	&self.1
+    }
+}
+
+impl<C: fmt::Display, E: ?Sized + fmt::Display> fmt::Display for Chain<C, E>
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	write!(f, "{}: {}", self.0, &self.1)
+    }
+}
+impl<C: fmt::Debug, E: ?Sized + fmt::Debug> fmt::Debug for Chain<C, E>
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	f.debug_list()
+	    .entry(&self.0)
+	    .entry(&&self.1)
+	    .finish()
+    }
+}
+
+impl<E> WrappedOSError<E>
+{
+    /// Add higher-level context `ctx` over the existing context, keeping the OS error.
+    ///
+    /// The resulting error displays as `ctx: <existing context>`. This can be repeated as the error propagates to build a chain.
+    #[inline]
+    pub fn with_context<C>(self, ctx: C) -> WrappedOSError<Chain<C, E>>
+    {
+	WrappedOSError(self.0, Chain(ctx, self.1))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn context_chain()
+    {
+	let error = WrappedOSError::from_os_error(libc::ENOENT, "opening file")
+	    .with_context("loading config")
+	    .with_context(format!("starting {}", "server"));
+	assert_eq!(error.to_string(), "starting server: loading config: opening file");
+	assert_eq!(error.raw_error(), libc::ENOENT);
+	assert_eq!(error.context().inner().outer(), &"loading config");
+	assert!(format!("{error:?}").contains(r#"["starting server", ["loading config", "opening file"]]"#));
+    }
+}
@@ -76,6 +76,108 @@ macro_rules! c_try {
 }
 pub(crate) use c_try;
 
+/// Invoke `call` until it either succeeds, or fails with an error other than `EINTR`.
+///
+/// `is_err` decides if a return value of `call` is a failure (in which case `errno` is checked.)
+/// The final return value is returned, so `errno` still holds the error if it is a failure.
+#[inline]
+pub(crate) fn retry_eintr<T>(mut call: impl FnMut() -> T, is_err: impl Fn(&T) -> bool) -> T
+{
+    loop {
+	let res = call();
+	if is_err(&res) && unsafe { *libc::__errno_location() } == libc::EINTR {
+	    continue;
+	}
+	break res;
+    }
+}
+
+/// Invoke an (implicitly `unsafe`) C call, re-issuing it while it fails with `EINTR`. See `retry_eintr()`.
+///
+/// * `c_retry!(call => invalid)`: The call fails if it returns `invalid`.
+/// * `c_retry!(call => if func)`: The call fails if `func(&res)` returns true.
+macro_rules! c_retry {
+    ($call:expr => if $func:expr) => {
+	$crate::ffi::retry_eintr(|| {
+	    #[allow(unused_unsafe)]
+	    unsafe { $call }
+	}, $func)
+    };
+    ($call:expr => $invalid:literal) => {
+	$crate::ffi::c_retry!($call => if |res| *res == $invalid)
+    };
+}
+pub(crate) use c_retry;
+
+/// Define a transparent newtype over a C flags integer, with a named constant for each flag, that can be combined with `|`.
+macro_rules! c_flags {
+    ($(#[$outer:meta])* $vis:vis struct $name:ident: $repr:ty {
+	$($(#[$inner:meta])* const $flag:ident = $value:expr;)*
+    }) => {
+	$(#[$outer])*
+	#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Default)]
+	#[repr(transparent)]
+	$vis struct $name($repr);
+
+	#[allow(unused)]
+	impl $name
+	{
+	    $(
+		$(#[$inner])*
+		pub const $flag: Self = Self($value);
+	    )*
+
+	    /// No flags.
+	    #[inline]
+	    pub const fn empty() -> Self
+	    {
+		Self(0)
+	    }
+
+	    /// The raw flags value passed to the C call.
+	    #[inline]
+	    pub const fn bits(&self) -> $repr
+	    {
+		self.0
+	    }
+
+	    /// Check if every flag in `other` is also set in `self`.
+	    #[inline]
+	    pub const fn contains(&self, other: Self) -> bool
+	    {
+		(self.0 & other.0) == other.0
+	    }
+
+	    /// Check if no flags are set.
+	    #[inline]
+	    pub const fn is_empty(&self) -> bool
+	    {
+		self.0 == 0
+	    }
+	}
+
+	impl ::std::ops::BitOr for $name
+	{
+	    type Output = Self;
+	    #[inline]
+	    fn bitor(self, rhs: Self) -> Self::Output
+	    {
+		Self(self.0 | rhs.0)
+	    }
+	}
+
+	impl ::std::ops::BitOrAssign for $name
+	{
+	    #[inline]
+	    fn bitor_assign(&mut self, rhs: Self)
+	    {
+		self.0 |= rhs.0;
+	    }
+	}
+    };
+}
+pub(crate) use c_flags;
+
 /// Error context for a failed C call.
 /// Returns the invalid return value, the `errno` error, and a message.
 #[derive(Debug)]
@@ -200,3 +302,24 @@ impl<'a, T> From<FFIError<'a, T>> for io::Error
     }
 }
 
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn retry_eintr_reissues_interrupted_calls()
+    {
+	let mut calls = 0;
+	let res = retry_eintr(|| {
+	    calls += 1;
+	    unsafe {
+		*libc::__errno_location() = if calls < 3 { libc::EINTR } else { libc::EBADF };
+	    }
+	    -1
+	}, |&res| res == -1);
+	assert_eq!((res, calls), (-1, 3));
+	assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+    }
+}
@@ -13,13 +13,35 @@ use raw::*;
 
 mod managed;
 mod unmanaged;
+mod transfer;
+mod scm;
+mod eventfd;
+#[cfg(feature="tokio")]
+mod async_fd;
+#[cfg(feature="uffd")]
+mod uffd;
 
 pub use self::{
     managed::*,
     unmanaged::*,
+    transfer::*,
+    scm::*,
+    eventfd::*,
 };
+#[cfg(feature="tokio")]
+pub use async_fd::*;
+#[cfg(feature="uffd")]
+pub use uffd::*;
 
 pub mod memory;
+pub mod maps;
+
+/// Convert a file offset or length into an `off_t` for a C call.
+#[inline]
+fn to_off_t(n: u64) -> io::Result<libc::off_t>
+{
+    libc::off_t::try_from(n).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
 
 #[derive(Debug)]
 enum MaybeMappedInner<T>
@@ -89,6 +111,45 @@ pub fn try_map_or_cloned<F: io::Read + AsRawFd + IntoRawFd>(file: F, perm: Perm,
     MappedFile::new(file, len, perm, flags)
 }
 
+c_flags! {
+    /// Flags for `mlock_all()`, combined with `|`.
+    pub struct MlockAllFlags: libc::c_int {
+	/// Lock all pages currently mapped into the process.
+	const CURRENT = libc::MCL_CURRENT;
+	/// Lock all pages mapped into the process in the future.
+	const FUTURE = libc::MCL_FUTURE;
+	/// Used with `CURRENT` and/or `FUTURE`: Lock pages only as they are faulted in, instead of populating them all immediately.
+	const ON_FAULT = libc::MCL_ONFAULT;
+    }
+}
+
+/// Lock the pages of the whole process into memory via `mlockall()`.
+///
+/// This affects every mapping in the process (not just `MappedFile`s), so it is a free function rather than a method. Real-time programs usually call it once at startup with `MlockAllFlags::CURRENT | MlockAllFlags::FUTURE`.
+///
+/// # Returns
+/// If `mlockall()` fails, the error is returned unchanged (e.g. `ENOMEM` if `RLIMIT_MEMLOCK` is exceeded, or `EPERM` if the process is not privileged to lock memory.)
+#[inline]
+pub fn mlock_all(flags: MlockAllFlags) -> io::Result<()>
+{
+    if unsafe { libc::mlockall(flags.bits()) } != 0 {
+	Err(io::Error::last_os_error())
+    } else {
+	Ok(())
+    }
+}
+
+/// Unlock all pages of the process locked by `mlock_all()` (or otherwise), via `munlockall()`.
+#[inline]
+pub fn munlock_all() -> io::Result<()>
+{
+    if unsafe { libc::munlockall() } != 0 {
+	Err(io::Error::last_os_error())
+    } else {
+	Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests
 {
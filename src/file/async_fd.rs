@@ -0,0 +1,147 @@
+//! Driving a `ManagedFD` with Tokio's reactor
+use super::*;
+use std::{
+    pin::Pin,
+    task::{
+	Context,
+	Poll,
+	ready,
+    },
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+    ReadBuf,
+    unix::AsyncFd,
+};
+
+/// A `ManagedFD` registered with Tokio's reactor, implementing `AsyncRead` and `AsyncWrite`. See `ManagedFD::into_async()`.
+#[derive(Debug)]
+pub struct AsyncManagedFD(AsyncFd<ManagedFD>);
+
+impl ManagedFD
+{
+    /// Register this file descriptor with the current Tokio runtime's reactor, so it can be read from and written to asynchronously.
+    ///
+    /// # Nonblocking
+    /// The file descriptor **must** already be nonblocking (see `set_status_flags()` and `OpenFlags::NONBLOCK`), otherwise reads and writes would block the runtime's worker thread.
+    /// Only pollable files (e.g. pipes, sockets, eventfds) can be registered: Regular files (including `MemoryFile`s) are always ready, so `epoll()` refuses them with `EPERM`.
+    ///
+    /// # Returns
+    /// If the file descriptor is not nonblocking, an error of kind `InvalidInput` is returned.
+    /// If `fcntl()` fails, or the file cannot be registered with the reactor, then the error is returned.
+    ///
+    /// # Panics
+    /// If called outside of a Tokio runtime with IO enabled.
+    pub fn into_async(self) -> io::Result<AsyncManagedFD>
+    {
+	if !self.status_flags()?.contains(OpenFlags::NONBLOCK) {
+	    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("File descriptor {} is not nonblocking", self.as_raw_fd())));
+	}
+	AsyncFd::new(self).map(AsyncManagedFD)
+    }
+}
+
+impl AsyncManagedFD
+{
+    /// A reference to the file descriptor.
+    #[inline]
+    pub fn get_ref(&self) -> &ManagedFD
+    {
+	self.0.get_ref()
+    }
+
+    /// Deregister the file descriptor from the reactor, and return it.
+    #[inline]
+    pub fn into_inner(self) -> ManagedFD
+    {
+	self.0.into_inner()
+    }
+}
+
+impl AsRawFd for AsyncManagedFD
+{
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+	self.0.as_raw_fd()
+    }
+}
+
+impl AsyncRead for AsyncManagedFD
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>>
+    {
+	loop {
+	    let mut guard = ready!(self.0.poll_read_ready(cx))?;
+	    let unfilled = buf.initialize_unfilled();
+	    match guard.try_io(|fd| match crate::ffi::c_retry!(libc::read(fd.as_raw_fd(), unfilled.as_mut_ptr() as *mut _, unfilled.len()) => -1) {
+		-1 => Err(io::Error::last_os_error()),
+		n => Ok(n as usize),
+	    }) {
+		Ok(result) => return Poll::Ready(result.map(|n| buf.advance(n))),
+		// Not actually ready: the readiness was cleared, so wait again.
+		Err(_would_block) => continue,
+	    }
+	}
+    }
+}
+
+impl AsyncWrite for AsyncManagedFD
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>
+    {
+	loop {
+	    let mut guard = ready!(self.0.poll_write_ready(cx))?;
+	    match guard.try_io(|fd| match crate::ffi::c_retry!(libc::write(fd.as_raw_fd(), buf.as_ptr() as *const _, buf.len()) => -1) {
+		-1 => Err(io::Error::last_os_error()),
+		n => Ok(n as usize),
+	    }) {
+		Ok(result) => return Poll::Ready(result),
+		Err(_would_block) => continue,
+	    }
+	}
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>>
+    {
+	Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>>
+    {
+	Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::future::poll_fn;
+
+    #[test]
+    fn pipe_round_trip()
+    {
+	let rt = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+	rt.block_on(async {
+	    assert_eq!(ManagedFD::from(memory::MemoryFile::new().unwrap()).into_async().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+	    let mut fds = [0; 2];
+	    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) }, 0);
+	    let (mut rx, mut tx) = unsafe { (ManagedFD::from_raw_fd(fds[0]).into_async().unwrap(), ManagedFD::from_raw_fd(fds[1]).into_async().unwrap()) };
+
+	    let reader = tokio::task::spawn(async move {
+		let mut data = [0; 5];
+		let mut buf = ReadBuf::new(&mut data);
+		while buf.remaining() > 0 {
+		    poll_fn(|cx| Pin::new(&mut rx).poll_read(cx, &mut buf)).await.unwrap();
+		}
+		data
+	    });
+	    assert_eq!(poll_fn(|cx| Pin::new(&mut tx).poll_write(cx, b"async")).await.unwrap(), 5);
+	    assert_eq!(&reader.await.unwrap(), b"async");
+	});
+    }
+}
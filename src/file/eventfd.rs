@@ -0,0 +1,146 @@
+//! A lightweight notification counter shared between threads or processes (`eventfd()`)
+use super::*;
+use libc::{
+    c_int,
+    EFD_CLOEXEC,
+    EFD_NONBLOCK,
+    EFD_SEMAPHORE,
+};
+
+c_flags! {
+    /// Flags for creating an `EventFd` with `eventfd()`, combined with `|`.
+    pub struct EventFdFlags: c_int {
+	/// Close the file descriptor on `exec()`. (This is the default for `EventFd::new()`.)
+	const CLOEXEC = EFD_CLOEXEC;
+	/// `EventFd::wait()` fails with `WouldBlock` instead of blocking when the counter is zero. Required to use the file descriptor with `mio` or `ManagedFD::into_async()`.
+	const NONBLOCK = EFD_NONBLOCK;
+	/// `EventFd::wait()` decrements the counter by 1 and returns 1, rather than returning the whole count and resetting it to 0.
+	const SEMAPHORE = EFD_SEMAPHORE;
+    }
+}
+
+/// A kernel-side 64-bit counter used as a readiness signal, e.g. between the producer and consumer of a ring-buffer.
+///
+/// `signal()` adds to the counter, and `wait()` blocks until it is non-zero and then takes from it. It is lighter than a pipe, as only a single counter is kept no matter how many times it is signalled.
+/// Like any file descriptor, it can be shared with child processes, or sent to another with `send_fd()`. To use it with `mio` or `tokio`, create it with `EventFdFlags::NONBLOCK` and use `into_inner()`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct EventFd(ManagedFD);
+
+impl EventFd
+{
+    /// Create a new event counter starting at 0, which is closed on `exec()`.
+    #[inline]
+    pub fn new() -> io::Result<Self>
+    {
+	Self::with_flags(0, EventFdFlags::CLOEXEC)
+    }
+
+    /// Create a new event counter starting at `initial`, with these flags.
+    ///
+    /// # Returns
+    /// If `eventfd()` fails, the error is returned.
+    pub fn with_flags(initial: u32, flags: EventFdFlags) -> io::Result<Self>
+    {
+	match unsafe { libc::eventfd(initial, flags.bits()) } {
+	    -1 => Err(io::Error::last_os_error()),
+	    fd => Ok(Self(unsafe { ManagedFD::take_unchecked(fd) })),
+	}
+    }
+
+    /// Add `n` to the counter, waking anything blocked in `wait()`.
+    ///
+    /// # Returns
+    /// If the counter would overflow past `u64::MAX - 1`, this blocks until it is read, or fails with `WouldBlock` if the counter is `NONBLOCK`.
+    /// If `n` is `u64::MAX`, an error of kind `InvalidInput` is returned.
+    pub fn signal(&self, n: u64) -> io::Result<()>
+    {
+	let buf = n.to_ne_bytes();
+	match c_retry!(libc::write(self.0.as_raw_fd(), buf.as_ptr() as *const _, buf.len()) => -1) {
+	    -1 => Err(io::Error::last_os_error()),
+	    _ => Ok(()),
+	}
+    }
+
+    /// Block until the counter is non-zero, then take from it.
+    ///
+    /// # Returns
+    /// The whole count (resetting the counter to 0), or 1 (decrementing it by 1) if the counter was created with `EventFdFlags::SEMAPHORE`.
+    /// If the counter is `NONBLOCK` and is 0, an error of kind `WouldBlock` is returned.
+    pub fn wait(&self) -> io::Result<u64>
+    {
+	let mut buf = [0u8; mem::size_of::<u64>()];
+	match c_retry!(libc::read(self.0.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) => -1) {
+	    -1 => Err(io::Error::last_os_error()),
+	    _ => Ok(u64::from_ne_bytes(buf)),
+	}
+    }
+
+    /// Consume into the managed file descriptor.
+    #[inline]
+    pub fn into_inner(self) -> ManagedFD
+    {
+	self.0
+    }
+}
+
+impl AsRawFd for EventFd
+{
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+	self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for EventFd
+{
+    #[inline]
+    fn into_raw_fd(self) -> RawFd {
+	self.0.into_raw_fd()
+    }
+}
+
+impl From<EventFd> for ManagedFD
+{
+    #[inline]
+    fn from(from: EventFd) -> Self
+    {
+	from.0
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn signal_wakes_waiter()
+    {
+	let event = EventFd::new().unwrap();
+	let waiter = {
+	    let event = event.clone();
+	    std::thread::spawn(move || {
+		let mut total = 0;
+		while total < 10 {
+		    total += event.wait().unwrap();
+		}
+		total
+	    })
+	};
+	for _ in 0..10 {
+	    event.signal(1).unwrap();
+	}
+	assert_eq!(waiter.join().unwrap(), 10);
+    }
+
+    #[test]
+    fn semaphore_nonblocking()
+    {
+	let event = EventFd::with_flags(2, EventFdFlags::SEMAPHORE | EventFdFlags::NONBLOCK | EventFdFlags::CLOEXEC).unwrap();
+	assert_eq!(event.wait().unwrap(), 1);
+	assert_eq!(event.wait().unwrap(), 1);
+	assert_eq!(event.wait().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+	assert_eq!(event.signal(u64::MAX).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
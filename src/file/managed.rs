@@ -11,16 +11,106 @@ use libc::{
     close,
 };
 
+c_flags! {
+    /// The file status flags of an open file description, as returned by `fcntl(F_GETFL)`.
+    ///
+    /// Any other bits reported by the kernel are preserved as-is.
+    pub struct OpenFlags: libc::c_int {
+	/// Opened write-only. (An access mode bit.)
+	const WRITE_ONLY = libc::O_WRONLY;
+	/// Opened for reading and writing. (An access mode bit.)
+	const READ_WRITE = libc::O_RDWR;
+	/// Writes always append to the end of the file.
+	const APPEND = libc::O_APPEND;
+	/// Operations do not block.
+	const NONBLOCK = libc::O_NONBLOCK;
+	/// Signal-driven I/O is enabled.
+	const ASYNC = libc::O_ASYNC;
+	/// I/O bypasses the page cache where possible.
+	const DIRECT = libc::O_DIRECT;
+	/// Reads do not update the file's access time.
+	const NOATIME = libc::O_NOATIME;
+	/// Writes complete as synchronised I/O data integrity completion.
+	const DSYNC = libc::O_DSYNC;
+	/// Writes complete as synchronised I/O file integrity completion.
+	const SYNC = libc::O_SYNC;
+    }
+}
+
+impl OpenFlags
+{
+    /// The bits `fcntl(F_SETFL)` is able to change. All others are ignored by the kernel.
+    pub const SETTABLE: Self = Self(libc::O_APPEND | libc::O_NONBLOCK | libc::O_ASYNC | libc::O_DIRECT | libc::O_NOATIME);
+
+    /// If the file description was opened for reading.
+    #[inline]
+    pub const fn is_readable(&self) -> bool
+    {
+	(self.0 & libc::O_ACCMODE) != libc::O_WRONLY
+    }
+
+    /// If the file description was opened for writing.
+    #[inline]
+    pub const fn is_writable(&self) -> bool
+    {
+	(self.0 & libc::O_ACCMODE) != libc::O_RDONLY
+    }
+}
+
+/// Advice to the kernel about how a range of a file will be accessed. These will control `posix_fadvise()`.
+///
+/// Unlike `Advice`, this applies to the file's page cache itself, so it can prime the cache before the file is mapped.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Default)]
+pub enum FileAdvice
+{
+#[default]
+    Normal,
+    Sequential,
+    Random,
+    /// The data will be accessed once.
+    NoReuse,
+    /// The data will be accessed soon: start reading it into the page cache.
+    WillNeed,
+    /// The data will not be accessed soon: it may be dropped from the page cache.
+    DontNeed,
+}
+
+impl FileAdvice
+{
+#[inline(always)]
+    const fn get_fadv(self) -> libc::c_int
+    {
+        use libc::{
+            POSIX_FADV_NORMAL,
+            POSIX_FADV_SEQUENTIAL,
+            POSIX_FADV_RANDOM,
+            POSIX_FADV_NOREUSE,
+            POSIX_FADV_WILLNEED,
+            POSIX_FADV_DONTNEED,
+        };
+        match self {
+            Self::Normal => POSIX_FADV_NORMAL,
+            Self::Sequential => POSIX_FADV_SEQUENTIAL,
+            Self::Random => POSIX_FADV_RANDOM,
+            Self::NoReuse => POSIX_FADV_NOREUSE,
+            Self::WillNeed => POSIX_FADV_WILLNEED,
+            Self::DontNeed => POSIX_FADV_DONTNEED,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
 pub struct ManagedFD(UnmanagedFD);
 
+/// # Panics
+/// `clone()` panics if `dup()` fails (e.g. with `EMFILE` when the process is out of file descriptors.) Prefer `try_clone()` where that can happen.
 impl Clone for ManagedFD {
     fn clone(&self) -> Self {
 	Self(unsafe { UnmanagedFD::new_unchecked( c_try!(dup(self.0.get()) => if |x| x < 0; "dup(): failed to duplicate file descriptor {}", self.0.get()) ) })
     }
     fn clone_from(&mut self, source: &Self) {
-	c_try!(dup2(self.0.get(), source.0.get()) => -1; "dup2(): failed to set file descriptor {} to alias {}", self.0.get(), source.0.get());
+	c_try!(dup2(source.0.get(), self.0.get()) => -1; "dup2(): failed to set file descriptor {} to alias {}", self.0.get(), source.0.get());
     }
 }
 
@@ -44,6 +134,16 @@ impl ManagedFD
 	}
     }
 
+    /// Duplicate this file-descriptor, returning the error instead of panicking as `clone()` does.
+    ///
+    /// # Returns
+    /// If `dup()` fails (e.g. `EMFILE` when the process has too many open files), the error is returned.
+    #[inline]
+    pub fn try_clone(&self) -> io::Result<Self>
+    {
+	Self::alias(self)
+    }
+
     #[inline] 
     pub const fn take_raw(fd: RawFd) -> Self
     {
@@ -66,6 +166,155 @@ impl ManagedFD
 	std::mem::forget(self);
 	v
     }
+
+    /// Get the file status flags of the open file description via `fcntl(F_GETFL)`.
+    ///
+    /// # Note
+    /// These flags belong to the open file description, so they are shared with any `dup()`'d aliases of this file descriptor.
+    #[inline]
+    pub fn status_flags(&self) -> io::Result<OpenFlags>
+    {
+	match unsafe { libc::fcntl(self.0.get(), libc::F_GETFL) } {
+	    -1 => Err(io::Error::last_os_error()),
+	    flags => Ok(OpenFlags(flags)),
+	}
+    }
+
+    /// Set the file status flags of the open file description via `fcntl(F_SETFL)`.
+    ///
+    /// Only the bits in `OpenFlags::SETTABLE` are passed to the kernel; access mode and other bits in `flags` are ignored.
+    /// To change a single flag, modify the value returned by `status_flags()`.
+    #[inline]
+    pub fn set_status_flags(&self, flags: OpenFlags) -> io::Result<()>
+    {
+	if unsafe { libc::fcntl(self.0.get(), libc::F_SETFL, flags.0 & OpenFlags::SETTABLE.0) } != 0 {
+	    Err(io::Error::last_os_error())
+	} else {
+	    Ok(())
+	}
+    }
+
+    /// Advise the kernel on how the range `offset..offset+len` of the file will be accessed via `posix_fadvise()`. A `len` of 0 extends the range to the end of the file.
+    ///
+    /// # Returns
+    /// If `posix_fadvise()` fails, the error is returned (e.g. `ESPIPE` for pipes.)
+    ///
+    /// # Note
+    /// This is only a hint. On `tmpfs` (and so for `MemoryFile`s) the file has no backing store to read from, so it has no effect.
+    pub fn fadvise(&self, offset: u64, len: u64, advice: FileAdvice) -> io::Result<()>
+    {
+	// `posix_fadvise()` returns the error number instead of setting `errno`.
+	match unsafe { libc::posix_fadvise(self.0.get(), to_off_t(offset)?, to_off_t(len)?, advice.get_fadv()) } {
+	    0 => Ok(()),
+	    errno => Err(io::Error::from_raw_os_error(errno)),
+	}
+    }
+
+    /// Start reading the range `offset..offset+count` of the file into the page cache via `readahead()`, returning once the reads have been initiated.
+    ///
+    /// Unlike the `MADV_WILLNEED` hint of `MappedFile::advise(_, Some(true))`, this is guaranteed to initiate the reads, which makes it useful ahead of a sequential scan over a mapping of the file (see `Advice::Sequential`.)
+    ///
+    /// # Returns
+    /// If `readahead()` fails, the error is returned. It only works on regular files and block devices, and fails with `EINVAL` for other file types (e.g. pipes and sockets.)
+    #[inline]
+    pub fn readahead(&self, offset: u64, count: usize) -> io::Result<()>
+    {
+	if unsafe { libc::readahead(self.0.get(), to_off_t(offset)?, count) } != 0 {
+	    Err(io::Error::last_os_error())
+	} else {
+	    Ok(())
+	}
+    }
+
+    /// The `/proc/self/fd/<fd>` path of this file descriptor.
+    ///
+    /// # Note
+    /// This is Linux-specific, requires `/proc` to be mounted, and is only valid within this process (a child process that inherits the file descriptor can use `/proc/<parent pid>/fd/<fd>` or its own `/proc/self/fd/<fd>`.)
+    #[inline]
+    pub fn proc_path(&self) -> std::path::PathBuf
+    {
+	format!("/proc/self/fd/{}", self.0.get()).into()
+    }
+
+    /// Open the file again through `proc_path()`, creating a new open file description.
+    ///
+    /// Unlike `alias()`, the returned file has its own file offset and status flags, and `opts` can give it a different access mode.
+    ///
+    /// # Note
+    /// See `proc_path()`.
+    #[inline]
+    pub fn reopen(&self, opts: &std::fs::OpenOptions) -> io::Result<std::fs::File>
+    {
+	opts.open(self.proc_path())
+    }
+
+    fn flock(&self, operation: libc::c_int) -> io::Result<()>
+    {
+	if unsafe { libc::flock(self.0.get(), operation) } != 0 {
+	    Err(io::Error::last_os_error())
+	} else {
+	    Ok(())
+	}
+    }
+
+    /// Non-blocking `flock()`: `Ok(false)` if the lock is held elsewhere.
+    fn try_flock(&self, operation: libc::c_int) -> io::Result<bool>
+    {
+	match self.flock(operation | libc::LOCK_NB) {
+	    Ok(()) => Ok(true),
+	    Err(e) if e.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(false),
+	    Err(e) => Err(e),
+	}
+    }
+
+    /// Acquire an exclusive advisory lock on the file via `flock(LOCK_EX)`, blocking until it is available.
+    ///
+    /// # Note
+    /// `flock()` locks are advisory: they only coordinate with other processes that also lock the file, and do not prevent access (or mapping) by those that don't.
+    /// The lock belongs to the open file description, so it is shared by `dup()`'d aliases of this file descriptor and is released when all of them are closed (or by `unlock()`.)
+    /// Locking a different open file description of the same file (e.g. from a separate `open()`) will conflict, even within the same process.
+    #[inline]
+    pub fn lock_exclusive(&self) -> io::Result<()>
+    {
+	self.flock(libc::LOCK_EX)
+    }
+
+    /// Acquire a shared advisory lock on the file via `flock(LOCK_SH)`, blocking until it is available.
+    ///
+    /// # Note
+    /// See `lock_exclusive()`.
+    #[inline]
+    pub fn lock_shared(&self) -> io::Result<()>
+    {
+	self.flock(libc::LOCK_SH)
+    }
+
+    /// Attempt to acquire an exclusive advisory lock on the file without blocking.
+    ///
+    /// # Returns
+    /// `Ok(false)` if a conflicting lock is held, `Ok(true)` if the lock was acquired.
+    #[inline]
+    pub fn try_lock_exclusive(&self) -> io::Result<bool>
+    {
+	self.try_flock(libc::LOCK_EX)
+    }
+
+    /// Attempt to acquire a shared advisory lock on the file without blocking.
+    ///
+    /// # Returns
+    /// `Ok(false)` if a conflicting lock is held, `Ok(true)` if the lock was acquired.
+    #[inline]
+    pub fn try_lock_shared(&self) -> io::Result<bool>
+    {
+	self.try_flock(libc::LOCK_SH)
+    }
+
+    /// Release any advisory lock held on the file by this open file description via `flock(LOCK_UN)`.
+    #[inline]
+    pub fn unlock(&self) -> io::Result<()>
+    {
+	self.flock(libc::LOCK_UN)
+    }
 }
 
 impl ops::Drop for ManagedFD
@@ -114,4 +363,207 @@ impl From<ManagedFD> for std::fs::File
     }
 }
 
+impl From<std::os::fd::OwnedFd> for ManagedFD
+{
+    #[inline]
+    fn from(from: std::os::fd::OwnedFd) -> Self
+    {
+	unsafe {
+	    Self::from_raw_fd(from.into_raw_fd())
+	}
+    }
+}
+
+impl From<ManagedFD> for std::os::fd::OwnedFd
+{
+    /// Transfer ownership of the fd. `from` is forgotten, so it is only closed by the returned `OwnedFd`.
+    #[inline]
+    fn from(from: ManagedFD) -> Self
+    {
+	unsafe {
+	    Self::from_raw_fd(from.into_raw_fd())
+	}
+    }
+}
+
 raw::impl_io_for_fd!(ManagedFD => .0.get());
+
+/// Registers the file descriptor itself with `mio::unix::SourceFd`.
+///
+/// # Note
+/// As with `SourceFd`, the file descriptor should be nonblocking (see `set_status_flags()`), and must be pollable (e.g. a pipe or eventfd, not a regular file.)
+#[cfg(feature="mio")]
+impl mio::event::Source for ManagedFD
+{
+    #[inline]
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()>
+    {
+	mio::unix::SourceFd(&self.0.get()).register(registry, token, interests)
+    }
+
+    #[inline]
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()>
+    {
+	mio::unix::SourceFd(&self.0.get()).reregister(registry, token, interests)
+    }
+
+    #[inline]
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+	mio::unix::SourceFd(&self.0.get()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn status_flags_nonblock()
+    {
+	let fd = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	let flags = fd.status_flags().unwrap();
+	assert!(flags.is_readable() && flags.is_writable());
+	assert!(!flags.contains(OpenFlags::NONBLOCK));
+
+	fd.set_status_flags(flags | OpenFlags::NONBLOCK).unwrap();
+	assert!(fd.status_flags().unwrap().contains(OpenFlags::NONBLOCK));
+
+	fd.set_status_flags(flags).unwrap();
+	assert_eq!(fd.status_flags().unwrap(), flags);
+    }
+
+    #[test]
+    fn fadvise_regular_and_pipe()
+    {
+	let fd = ManagedFD::from(memory::MemoryFile::with_size(4096).unwrap());
+	fd.fadvise(0, 0, FileAdvice::WillNeed).unwrap();
+	fd.fadvise(0, 4096, FileAdvice::Sequential).unwrap();
+
+	let mut fds = [0; 2];
+	assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+	let (rx, _tx) = unsafe { (ManagedFD::from_raw_fd(fds[0]), ManagedFD::from_raw_fd(fds[1])) };
+	assert_eq!(rx.fadvise(0, 0, FileAdvice::WillNeed).unwrap_err().raw_os_error(), Some(libc::ESPIPE));
+    }
+
+    #[test]
+    fn readahead_regular_and_pipe()
+    {
+	let fd = ManagedFD::from(memory::MemoryFile::with_size(4096).unwrap());
+	fd.readahead(0, 4096).unwrap();
+
+	let mut fds = [0; 2];
+	assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+	let (rx, _tx) = unsafe { (ManagedFD::from_raw_fd(fds[0]), ManagedFD::from_raw_fd(fds[1])) };
+	assert_eq!(rx.readahead(0, 4096).unwrap_err().raw_os_error(), Some(libc::EINVAL));
+    }
+
+    #[test]
+    fn clone_from_aliases_source()
+    {
+	use std::io::{Read, Write};
+	fn read_all(fd: &mut ManagedFD) -> Vec<u8>
+	{
+	    assert_eq!(unsafe { libc::lseek(fd.as_raw_fd(), 0, libc::SEEK_SET) }, 0);
+	    let mut out = Vec::new();
+	    fd.read_to_end(&mut out).unwrap();
+	    out
+	}
+
+	let mut a = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	let mut b = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	a.write_all(b"first").unwrap();
+	b.write_all(b"second").unwrap();
+	let raw = a.as_raw_fd();
+
+	a.clone_from(&b);
+	// `a` keeps its fd number, but now refers to `b`'s file.
+	assert_eq!(a.as_raw_fd(), raw);
+	assert_eq!(read_all(&mut a), b"second");
+	assert_eq!(read_all(&mut b), b"second");
+    }
+
+    #[test]
+    fn try_clone_fd_exhaustion()
+    {
+	let fd = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	let copy = fd.try_clone().unwrap();
+	assert_ne!(copy.as_raw_fd(), fd.as_raw_fd());
+
+	// Lower the fd limit in a child, so the rest of the test process is unaffected.
+	crate::tests::in_child(|| {
+	    let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+	    let lowered = unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } == 0;
+	    lowered && fd.try_clone().map(drop).map_err(|e| e.raw_os_error()) == Err(Some(libc::EMFILE))
+	});
+    }
+
+    #[test]
+    fn owned_fd_round_trip()
+    {
+	use std::os::fd::OwnedFd;
+	let fd = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	let raw = fd.as_raw_fd();
+
+	let owned = OwnedFd::from(fd);
+	assert_eq!(owned.as_raw_fd(), raw);
+	let fd = ManagedFD::from(owned);
+	assert_eq!(fd.as_raw_fd(), raw);
+	// Neither conversion closed the fd.
+	assert_ne!(unsafe { libc::fcntl(raw, libc::F_GETFD) }, -1);
+    }
+
+    #[cfg(feature="mio")]
+    #[test]
+    fn mio_poll_pipe()
+    {
+	use std::io::Write;
+	use mio::{Events, Interest, Poll, Token};
+	let mut fds = [0; 2];
+	assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) }, 0);
+	let (mut rx, mut tx) = unsafe { (ManagedFD::from_raw_fd(fds[0]), ManagedFD::from_raw_fd(fds[1])) };
+
+	let mut poll = Poll::new().unwrap();
+	poll.registry().register(&mut rx, Token(1), Interest::READABLE).unwrap();
+	tx.write_all(b"ready").unwrap();
+
+	let mut events = Events::with_capacity(4);
+	poll.poll(&mut events, Some(std::time::Duration::from_secs(5))).unwrap();
+	assert!(events.iter().any(|e| e.token() == Token(1) && e.is_readable()));
+	poll.registry().deregister(&mut rx).unwrap();
+    }
+
+    #[test]
+    fn reopen_has_own_offset()
+    {
+	use std::io::{Read, Write};
+	let mut a = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	a.write_all(b"hello").unwrap();
+	assert_eq!(a.proc_path(), std::path::Path::new(&format!("/proc/self/fd/{}", a.as_raw_fd())));
+
+	// Unlike `alias()`, the offset of `a` (now at the end) is not shared.
+	let mut b = a.reopen(std::fs::OpenOptions::new().read(true)).unwrap();
+	let mut buf = String::new();
+	b.read_to_string(&mut buf).unwrap();
+	assert_eq!(buf, "hello");
+	assert_eq!(unsafe { libc::lseek(a.as_raw_fd(), 0, libc::SEEK_CUR) }, 5);
+    }
+
+    #[test]
+    fn flock_conflicts()
+    {
+	let a = ManagedFD::from(memory::MemoryFile::new().unwrap());
+	// A separate open file description of the same file.
+	let b = unsafe { ManagedFD::from_raw_fd(std::fs::File::open(format!("/proc/self/fd/{}", a.as_raw_fd())).unwrap().into_raw_fd()) };
+
+	a.lock_exclusive().unwrap();
+	assert!(!b.try_lock_exclusive().unwrap());
+	assert!(!b.try_lock_shared().unwrap());
+	a.unlock().unwrap();
+
+	assert!(b.try_lock_shared().unwrap());
+	a.lock_shared().unwrap();
+	assert!(!a.try_lock_exclusive().unwrap());
+    }
+}
@@ -0,0 +1,212 @@
+//! Inspecting the memory mappings of this process, as listed in `/proc/self/maps`.
+//!
+//! Useful for checking that a mapping exists with the expected permissions, e.g. in tests and diagnostics.
+use super::*;
+use std::{
+    fmt,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+};
+
+/// Location in which the kernel lists the mappings of this process.
+pub const MAPS_LOCATION: &str = "/proc/self/maps";
+
+/// The permissions of a mapping, as listed in `/proc/self/maps` (e.g. `rw-p`.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MapPerms
+{
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    /// `MAP_SHARED`, rather than private (copy-on-write.)
+    pub shared: bool,
+}
+
+impl MapPerms
+{
+    /// Parse the 4-character permission field (e.g. `r-xp`.)
+    fn parse(field: &[u8]) -> Option<Self>
+    {
+	let &[r, w, x, s] = field else {
+	    return None;
+	};
+	let flag = |c, set| match c {
+	    b'-' => Some(false),
+	    c if c == set => Some(true),
+	    _ => None,
+	};
+	Some(Self {
+	    read: flag(r, b'r')?,
+	    write: flag(w, b'w')?,
+	    execute: flag(x, b'x')?,
+	    shared: match s {
+		b's' => true,
+		b'p' => false,
+		_ => return None,
+	    },
+	})
+    }
+}
+
+impl fmt::Display for MapPerms
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	use fmt::Write;
+	f.write_char(if self.read { 'r' } else { '-' })?;
+	f.write_char(if self.write { 'w' } else { '-' })?;
+	f.write_char(if self.execute { 'x' } else { '-' })?;
+	f.write_char(if self.shared { 's' } else { 'p' })
+    }
+}
+
+/// A single mapping of this process. See `find_mapping()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MapEntry
+{
+    /// The first address of the mapping.
+    pub start: usize,
+    /// One past the last address of the mapping.
+    pub end: usize,
+    pub perms: MapPerms,
+    /// The offset into the file the mapping starts at.
+    pub offset: u64,
+    /// The file mapped, or a pseudo-path such as `[heap]` or `[stack]`. `None` for anonymous mappings.
+    ///
+    /// # Note
+    /// A file that has been unlinked since it was mapped has ` (deleted)` appended by the kernel, and a `MemoryFile` shows up as `/memfd:<name>`.
+    pub path: Option<PathBuf>,
+}
+
+impl MapEntry
+{
+    /// Whether `addr` lies within the mapping.
+    #[inline]
+    pub fn contains(&self, addr: usize) -> bool
+    {
+	(self.start..self.end).contains(&addr)
+    }
+
+    /// Parse one line of `/proc/self/maps`.
+    ///
+    /// The line is taken as bytes, since the path of a mapped file is not necessarily valid UTF-8.
+    ///
+    /// # Returns
+    /// `None` if the line is malformed.
+    fn parse(line: &[u8]) -> Option<Self>
+    {
+	/// Strip the leading spaces off `s`.
+	fn trim_spaces(s: &[u8]) -> &[u8]
+	{
+	    &s[s.iter().position(|&b| b != b' ').unwrap_or(s.len())..]
+	}
+	/// Take the next space-separated field off the front of `rest`.
+	fn next_field<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]>
+	{
+	    let s = trim_spaces(rest);
+	    let (field, tail) = s.split_at(s.iter().position(|&b| b == b' ').unwrap_or(s.len()));
+	    *rest = tail;
+	    Some(field).filter(|f| !f.is_empty())
+	}
+	/// Parse a hexadecimal field.
+	fn hex<N: TryFrom<u64>>(field: &[u8]) -> Option<N>
+	{
+	    u64::from_str_radix(std::str::from_utf8(field).ok()?, 16).ok()?.try_into().ok()
+	}
+
+	let mut rest = line;
+	let range = next_field(&mut rest)?;
+	let split = range.iter().position(|&b| b == b'-')?;
+	let perms = MapPerms::parse(next_field(&mut rest)?)?;
+	let offset = hex(next_field(&mut rest)?)?;
+	let _device = next_field(&mut rest)?;
+	let _inode = next_field(&mut rest)?;
+	// The path may itself contain spaces, so it is the rest of the line.
+	let path = trim_spaces(rest);
+	Some(Self {
+	    start: hex(&range[..split])?,
+	    end: hex(&range[(split + 1)..])?,
+	    perms,
+	    offset,
+	    path: (!path.is_empty()).then(|| PathBuf::from(OsStr::from_bytes(path))),
+	})
+    }
+}
+
+/// Read all of the mappings of this process from `/proc/self/maps`, in ascending address order.
+///
+/// Malformed lines are skipped, the rest are still returned. Paths that are not valid UTF-8 are kept as they are.
+///
+/// # Returns
+/// If `/proc/self/maps` cannot be read.
+pub fn read_mappings() -> io::Result<Vec<MapEntry>>
+{
+    Ok(std::fs::read(MAPS_LOCATION)?
+       .split(|&b| b == b'\n')
+       .filter_map(MapEntry::parse)
+       .collect())
+}
+
+/// Find the mapping of this process containing the address `addr`.
+///
+/// # Note
+/// The kernel merges adjacent mappings with the same permissions and backing, so the entry returned may extend beyond a single `mmap()` call. Likewise, a single mapping that has been partially `mprotect()`ed shows up as several entries.
+///
+/// # Returns
+/// `None` if `addr` is not mapped. If `/proc/self/maps` cannot be read, then the error is returned.
+pub fn find_mapping(addr: usize) -> io::Result<Option<MapEntry>>
+{
+    Ok(read_mappings()?.into_iter().find(|entry| entry.contains(addr)))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_lines()
+    {
+	let entry = MapEntry::parse(b"7f0000000000-7f0000002000 r-xs 00001000 00:05 1234                       /memfd:my file (deleted)").unwrap();
+	assert_eq!((entry.start, entry.end, entry.offset), (0x7f00_0000_0000, 0x7f00_0000_2000, 0x1000));
+	assert_eq!(entry.perms.to_string(), "r-xs");
+	assert_eq!(entry.path.as_deref(), Some(std::path::Path::new("/memfd:my file (deleted)")));
+
+	let anon = MapEntry::parse(b"1000-2000 ---p 00000000 00:00 0 ").unwrap();
+	assert_eq!(anon.path, None);
+	assert_eq!(anon.perms, MapPerms::default());
+
+	assert_eq!(MapEntry::parse(b"1000-2000 rwzp 00000000 00:00 0"), None);
+	assert_eq!(MapEntry::parse(b"garbage"), None);
+	assert_eq!(MapEntry::parse(b""), None);
+
+	let entry = MapEntry::parse(b"1000-2000 rw-p 00000000 08:01 42 /tmp/not-utf8-\xff\xfe").unwrap();
+	assert_eq!(entry.path.unwrap().as_os_str().as_bytes(), b"/tmp/not-utf8-\xff\xfe");
+    }
+
+    #[test]
+    fn find_own_mapping()
+    {
+	let map = MappedFile::new(memory::MemoryFile::with_size(get_page_size()).unwrap(), get_page_size(), Perm::Readonly, Flags::Shared).unwrap();
+	let entry = find_mapping(map.address()).unwrap().expect("mapping not listed");
+	assert!(entry.perms.read && !entry.perms.write && entry.perms.shared);
+	assert!(entry.path.unwrap().to_string_lossy().starts_with("/memfd:"));
+    }
+
+    #[test]
+    fn non_utf8_path()
+    {
+	let mut name = b"mapped-file-maps-\xff-".to_vec();
+	name.extend_from_slice(std::process::id().to_string().as_bytes());
+	let path = std::env::temp_dir().join(OsStr::from_bytes(&name));
+	let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	file.set_len(get_page_size() as u64).unwrap();
+
+	let map = MappedFile::new(file, get_page_size(), Perm::Readonly, Flags::Shared).unwrap();
+	// The file has been unlinked, so the kernel appends ` (deleted)` to its path.
+	let entry = find_mapping(map.address()).unwrap().expect("mapping not listed");
+	assert!(entry.path.unwrap().as_os_str().as_bytes().starts_with(path.as_os_str().as_bytes()));
+    }
+}
@@ -8,8 +8,13 @@ use libc::{
     memfd_create,
     MFD_CLOEXEC,
     MFD_HUGETLB,
+    MFD_ALLOW_SEALING,
+    MFD_NOEXEC_SEAL,
 
     ftruncate,
+    fallocate,
+    FALLOC_FL_PUNCH_HOLE,
+    FALLOC_FL_KEEP_SIZE,
 };
 use std::{
     ffi::CStr,
@@ -30,6 +35,23 @@ static UNNAMED: &'static CStr = unsafe {
 
 const DEFAULT_FLAGS: c_uint = MFD_CLOEXEC;
 
+c_flags! {
+    /// Flags for creating a `MemoryFile` with `memfd_create()`, combined with `|`.
+    pub struct MemfdFlags: c_uint {
+	/// Close the file descriptor on `exec()`. (This is the default for `MemoryFile::new()`.)
+	const CLOEXEC = MFD_CLOEXEC;
+	/// Allow seals to be placed on the file with `fcntl(F_ADD_SEALS)`.
+	const ALLOW_SEALING = MFD_ALLOW_SEALING;
+	/// Back the file with huge-pages of the default size.
+	const HUGETLB = MFD_HUGETLB;
+	/// The file is created without execute permission, and is sealed against `chmod()`ing it executable, so it can never be `exec()`'d. This implies `ALLOW_SEALING`.
+	///
+	/// # Note
+	/// Kernels older than 6.3 do not know this flag, and `memfd_create()` fails with `EINVAL`.
+	const NOEXEC_SEAL = MFD_NOEXEC_SEAL;
+    }
+}
+
 #[inline(always)]
 //XXX: Is the static bound required here?
 /// Create a raw, unmanaged, memory file with these flags and this name.
@@ -42,6 +64,10 @@ pub unsafe fn create_raw(name: impl AsRef<CStr>, flags: c_uint) -> io::Result<Un
 }
 
 /// A physical-memory backed file
+///
+/// # Equality
+/// The derived `PartialEq` (and `Ord`, `Hash`) compare the *file descriptor numbers*, not the files: two memory files with identical contents compare unequal, and so does a `clone()` (a `dup()`'d alias of the same file.)
+/// To compare the contents of two memory files, use `content_eq()`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
 pub struct MemoryFile(ManagedFD);
@@ -93,6 +119,23 @@ impl MemoryFile
 	};
 	Ok(Self(managed))
     }
+    /// Create a new, empty, memory file with no name and these flags.
+    ///
+    /// # Returns
+    /// If `memfd_create()` fails, the error is returned. Notably, `EINVAL` if the kernel does not know one of the flags (see `MemfdFlags::NOEXEC_SEAL`), in which case the caller can retry without it.
+    #[inline]
+    pub fn with_flags(flags: MemfdFlags) -> io::Result<Self>
+    {
+	unsafe { create_raw(UNNAMED, flags.bits()) }
+	.map(ManagedFD::take)
+	    .map(Self)
+    }
+
+    /// Resize the memory file to `value` bytes via `ftruncate()`.
+    ///
+    /// # Warning
+    /// If the file is shrunk while a mapping over it extends past the new size, then any access to the truncated region of that mapping will raise `SIGBUS`.
+    /// Unmap any such mappings before shrinking the file, or use `MappedFile::try_resize_mapped()` which refuses to shrink the file below the length of its mapping.
     #[inline] 
     pub fn resize(&mut self, value: usize) -> io::Result<()>
     {
@@ -102,7 +145,114 @@ impl MemoryFile
 	    Err(io::Error::last_os_error())
 	}
     }
+
+    fn fallocate(&mut self, mode: libc::c_int, offset: u64, len: u64) -> io::Result<()>
+    {
+	if 0 == unsafe { fallocate(self.as_raw_fd(), mode, to_off_t(offset)?, to_off_t(len)?) } {
+	    Ok(())
+	} else {
+	    Err(io::Error::last_os_error())
+	}
+    }
+
+    /// Allocate backing pages for the range `offset..offset+len` of the memory file via `fallocate()`, growing the file if the range extends past its end.
+    ///
+    /// Once allocated, writes through a mapping over this range will not raise `SIGBUS` due to the memory being unavailable (`ENOSPC`.)
+    ///
+    /// # Returns
+    /// If `fallocate()` fails, the error is returned unchanged (e.g. `ENOSPC`, or `EOPNOTSUPP` if the mode is unsupported.)
+    #[inline]
+    pub fn allocate(&mut self, offset: u64, len: u64) -> io::Result<()>
+    {
+	self.fallocate(0, offset, len)
+    }
+
+    /// Deallocate the backing pages for the range `offset..offset+len` of the memory file via `fallocate(FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE)`, without changing its size.
+    ///
+    /// The range reads back as zeroes afterwards, including through any mappings over it.
+    ///
+    /// # Returns
+    /// If `fallocate()` fails, the error is returned unchanged (e.g. `EOPNOTSUPP` if the mode is unsupported.)
+    #[inline]
+    pub fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()>
+    {
+	self.fallocate(FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE, offset, len)
+    }
+
+    /// The current size of the memory file, via `fstat()`.
+    pub fn size(&self) -> io::Result<u64>
+    {
+	let mut stat = std::mem::MaybeUninit::uninit();
+	if unsafe { libc::fstat(self.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { stat.assume_init() }.st_size as u64)
+    }
+
+    /// The `/proc/self/fd/<fd>` path of this memory file, which a child process can use to open it by path.
+    ///
+    /// # Note
+    /// See `ManagedFD::proc_path()`.
+    #[inline]
+    pub fn proc_path(&self) -> std::path::PathBuf
+    {
+	self.0.proc_path()
+    }
+
+    /// Open the memory file again through `proc_path()`, with its own file offset and the access mode given by `opts`.
+    ///
+    /// # Note
+    /// See `ManagedFD::reopen()`.
+    #[inline]
+    pub fn reopen(&self, opts: &std::fs::OpenOptions) -> io::Result<std::fs::File>
+    {
+	self.0.reopen(opts)
+    }
+
+    /// Create a new, unnamed, memory file with a copy of the contents of this one.
+    ///
+    /// Unlike `clone()`, which aliases the same file, the returned file is independent of this one.
+    /// The contents are copied within the kernel with `copy_file_range()` where possible, falling back to copying through a mapping of this file if the kernel cannot (`ENOSYS`/`EXDEV`.) The file offset of this file is not changed.
+    pub fn deep_clone(&self) -> io::Result<Self>
+    {
+	let size = self.size()?;
+	let mut clone = Self::with_size(usize::try_from(size).map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))?)?;
+	let mut offset = 0;
+	while offset < size {
+	    let len = usize::try_from(size - offset).unwrap_or(usize::MAX);
+	    match copy_file_range(self, Some(offset), &clone, Some(offset), len) {
+		Ok(0) => break,
+		Ok(n) => offset += n as u64,
+		Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EXDEV)) => {
+		    let map = MappedFile::new(ManagedFD::alias(self)?, size as usize, Perm::Readonly, Flags::Shared)?;
+		    io::copy(&mut &map[..], &mut clone)?;
+		    break;
+		},
+		Err(e) => return Err(e),
+	    }
+	}
+	Ok(clone)
+    }
     
+    /// Compare the contents of this memory file with `other`'s.
+    ///
+    /// The sizes are compared first, then both files are mapped and their bytes compared. The file offsets are not changed.
+    ///
+    /// # Note
+    /// This is unrelated to `==`, which compares file descriptors (see the type's documentation.)
+    pub fn content_eq(&self, other: &MemoryFile) -> io::Result<bool>
+    {
+	let size = self.size()?;
+	if size != other.size()? {
+	    return Ok(false);
+	} else if size == 0 {
+	    return Ok(true);
+	}
+	let size = usize::try_from(size).map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))?;
+	let map = |file: &MemoryFile| MappedFile::new(ManagedFD::alias(file)?, size, Perm::Readonly, Flags::Shared);
+	Ok(map(self)?[..] == map(other)?[..])
+    }
+
     pub fn with_hugetlb(hugetlb: MapHugeFlag) -> io::Result<Self>
     {
 	unsafe { create_raw(UNNAMED, DEFAULT_FLAGS | (hugetlb.get_mask() as c_uint)) }
@@ -110,6 +260,9 @@ impl MemoryFile
 	    .map(Self)
     }
 
+    /// Create a new memory file of `size` bytes with no name, via `ftruncate()`.
+    ///
+    /// The pages are allocated lazily, when they are first written to. If memory runs out by then (e.g. `tmpfs` is full, or the process hits its memory limit), writing through a mapping raises `SIGBUS`. To allocate the pages up front, use `with_capacity()`.
     pub fn with_size(size: usize) -> io::Result<Self>
     {
 	let mut this = Self(unsafe { create_raw(UNNAMED, DEFAULT_FLAGS) }.map(ManagedFD::take)?);
@@ -117,6 +270,22 @@ impl MemoryFile
 	Ok(this)
     }
 
+    /// Create a new memory file of `size` bytes with no name, with every page allocated up front via `fallocate()`.
+    ///
+    /// Unlike `with_size()`, the memory is reserved immediately, so running out of it is reported here as an error (`ENOSPC`), and writes through a mapping of the file can never raise `SIGBUS` for lack of memory.
+    /// The allocated pages are zeroed, and count towards the memory use of the process from now on, whether or not they are ever touched.
+    ///
+    /// # Returns
+    /// If creating the file or `fallocate()` fails, the error is returned.
+    pub fn with_capacity(size: usize) -> io::Result<Self>
+    {
+	let mut this = Self(unsafe { create_raw(UNNAMED, DEFAULT_FLAGS) }.map(ManagedFD::take)?);
+	if size > 0 {
+	    this.allocate(0, size as u64)?;
+	}
+	Ok(this)
+    }
+
     #[inline] 
     pub fn with_size_hugetlb(size: usize, hugetlb: MapHugeFlag) -> io::Result<Self>
     {
@@ -190,6 +359,34 @@ impl NamedMemoryFile
 	this.resize(size)?;
 	Ok(this)
     }
+
+    /// The name of this memory file.
+    #[inline]
+    pub fn name(&self) -> &CStr
+    {
+	&self.0
+    }
+
+    /// Replace the stored name of this memory file.
+    ///
+    /// # Note
+    /// The kernel-side name of a memory file cannot be changed after creation, so this only affects the label stored here (the name seen in `/proc/self/fd/` is unchanged.)
+    #[inline]
+    pub fn set_name(&mut self, name: impl AsRef<str>)
+    {
+	self.0 = alloc_cstring(name.as_ref()).into();
+    }
+
+    /// Replace the stored name of this memory file, used as a builder-pattern alternative for `set_name()`.
+    ///
+    /// # Note
+    /// See `set_name()`: Only the label stored here is changed, not the kernel-side name.
+    #[inline]
+    pub fn with_name(mut self, name: impl AsRef<str>) -> Self
+    {
+	self.set_name(name);
+	self
+    }
 }
 
 impl AsRawFd for MemoryFile
@@ -235,3 +432,151 @@ impl From<MemoryFile> for std::fs::File
 }
 
 raw::impl_io_for_fd!(MemoryFile => .0.as_raw_fd());
+
+impl MappedFile<MemoryFile>
+{
+    /// Resize the backing memory file of this mapping to `size` bytes.
+    ///
+    /// Unlike `MemoryFile::resize()`, this refuses to shrink the file below the length of this mapping, since any access to the truncated region of the mapping would raise `SIGBUS`.
+    ///
+    /// # Returns
+    /// If `size` is less than `len()`, an error of kind `InvalidInput` is returned and the file is left unchanged.
+    /// Otherwise, if `ftruncate()` fails, then that error is returned.
+    ///
+    /// # Note
+    /// Only this mapping is checked. Other mappings over the same file (e.g. through a `dup()`'d file descriptor) are not known about.
+    pub fn try_resize_mapped(&mut self, size: usize) -> io::Result<()>
+    {
+	if size < self.len() {
+	    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Refusing to shrink memory file to {size} bytes while it is mapped with {} bytes", self.len())));
+	}
+	self.inner_mut().resize(size)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn resize_mapped_refuses_shrink()
+    {
+	let page = get_page_size();
+	let mut map = MappedFile::new(MemoryFile::with_size(page * 2).unwrap(), page * 2, Perm::ReadWrite, Flags::Shared).unwrap();
+	assert_eq!(map.try_resize_mapped(page).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+	map.try_resize_mapped(page * 3).expect("Failed to grow mapped file");
+	map[page * 2 - 1] = 0xff;
+    }
+
+    #[test]
+    fn content_eq_ignores_identity()
+    {
+	use std::io::Write;
+	let mut a = MemoryFile::new().unwrap();
+	let mut b = MemoryFile::new().unwrap();
+	assert!(a.content_eq(&b).unwrap());
+	assert_ne!(a, b);
+
+	a.write_all(b"same contents").unwrap();
+	assert!(!a.content_eq(&b).unwrap());
+	b.write_all(b"same contents").unwrap();
+	assert!(a.content_eq(&b).unwrap());
+	assert!(a.content_eq(&a.clone()).unwrap());
+	assert_ne!(a, a.clone());
+
+	// Same size, different bytes.
+	let mut map = MappedFile::new(b.clone(), 4, Perm::ReadWrite, Flags::Shared).unwrap();
+	map[0] = b'S';
+	assert!(!a.content_eq(&b).unwrap());
+    }
+
+    #[test]
+    fn allocate_and_punch_hole()
+    {
+	let page = get_page_size();
+	let mut file = MemoryFile::new().unwrap();
+	file.allocate(0, (page * 2) as u64).unwrap();
+	let mut map = MappedFile::new(file.clone(), page * 2, Perm::ReadWrite, Flags::Shared).unwrap();
+	map.as_slice_mut().fill(0xaa);
+
+	file.punch_hole(0, page as u64).unwrap();
+	assert!(map[..page].iter().all(|&b| b == 0));
+	assert!(map[page..].iter().all(|&b| b == 0xaa));
+    }
+
+    #[test]
+    fn reopen_by_proc_path()
+    {
+	use std::io::{Read, Write};
+	let mut file = NamedMemoryFile::new("reopen").unwrap();
+	file.write_all(b"hello").unwrap();
+	assert_eq!(file.proc_path(), std::path::Path::new(&format!("/proc/self/fd/{}", file.as_raw_fd())));
+
+	// The reopened file has its own offset, starting at 0.
+	let mut buf = String::new();
+	file.reopen(std::fs::OpenOptions::new().read(true)).unwrap().read_to_string(&mut buf).unwrap();
+	assert_eq!(buf, "hello");
+    }
+
+    #[test]
+    fn noexec_seal()
+    {
+	let page = get_page_size();
+	let mut file = match MemoryFile::with_flags(MemfdFlags::CLOEXEC | MemfdFlags::NOEXEC_SEAL) {
+	    Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return, // Kernel too old.
+	    file => file.unwrap(),
+	};
+	file.resize(page).unwrap();
+	assert!(MappedFile::new(file.clone(), page, Perm::ReadWrite, Flags::Shared).is_ok());
+
+	let mut stat = std::mem::MaybeUninit::uninit();
+	assert_eq!(unsafe { libc::fstat(file.as_raw_fd(), stat.as_mut_ptr()) }, 0);
+	assert_eq!(unsafe { stat.assume_init() }.st_mode & 0o111, 0);
+	assert_ne!(unsafe { libc::fchmod(file.as_raw_fd(), 0o755) }, 0, "Sealed memory file was made executable");
+    }
+
+    #[test]
+    fn with_capacity_allocates()
+    {
+	fn blocks(file: &MemoryFile) -> libc::blkcnt_t
+	{
+	    let mut stat = std::mem::MaybeUninit::uninit();
+	    assert_eq!(unsafe { libc::fstat(file.as_raw_fd(), stat.as_mut_ptr()) }, 0);
+	    unsafe { stat.assume_init() }.st_blocks
+	}
+	let page = get_page_size();
+	let lazy = MemoryFile::with_size(page * 4).unwrap();
+	let eager = MemoryFile::with_capacity(page * 4).unwrap();
+	assert_eq!(lazy.size().unwrap(), eager.size().unwrap());
+	assert_eq!(blocks(&lazy), 0);
+	// With transparent huge-pages for shared memory, a whole huge-page may be allocated.
+	assert!(blocks(&eager) as usize * 512 >= page * 4);
+	assert_eq!(MemoryFile::with_capacity(0).unwrap().size().unwrap(), 0);
+    }
+
+    #[test]
+    fn resize_shrink_faults_mapping()
+    {
+	let page = get_page_size();
+	let mut file = MemoryFile::with_size(page * 2).unwrap();
+	let map = MappedFile::new(file.clone(), page * 2, Perm::Readonly, Flags::Shared).unwrap();
+	// Shrinking the file underneath the mapping: the second page is still mapped, but no longer backed.
+	file.resize(page).unwrap();
+
+	// Accessing the truncated page raises `SIGBUS`, so do it in a child process.
+	match unsafe { libc::fork() } {
+	    -1 => panic!("fork() failed: {}", io::Error::last_os_error()),
+	    0 => unsafe {
+		std::ptr::read_volatile(&map[page]);
+		libc::_exit(0)
+	    },
+	    child => {
+		let mut status = 0;
+		assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+		assert!(libc::WIFSIGNALED(status), "Child did not fault on truncated page (status {status})");
+		assert_eq!(libc::WTERMSIG(status), libc::SIGBUS);
+	    },
+	}
+    }
+}
@@ -128,23 +128,11 @@ macro_rules! impl_io_for_fd {
     ($type:ty => .$($fd_path:tt)+) => {
 	const _:() = {
 	    use std::io;
-	    #[inline(always)]
-	    fn check_error() -> bool
-	    {
-		use libc::{
-		    EINTR,
-		    __errno_location,
-		};
-		match unsafe { *__errno_location() } {
-		    EINTR => true,
-		    _ => false,
-		}
-	    }
 	    
 	    impl io::Write for $type
 	    {
 		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-		    match unsafe { libc::write(self.$($fd_path)+, buf.as_ptr()  as *const _, buf.len()) } {
+		    match $crate::ffi::c_retry!(libc::write(self.$($fd_path)+, buf.as_ptr()  as *const _, buf.len()) => -1) {
 			-1 => Err(io::Error::last_os_error()),
 			n => Ok(n as usize)
 		    }
@@ -155,12 +143,9 @@ macro_rules! impl_io_for_fd {
 			buf = match buf {
 			    [] => break Ok(()),
 			    buf => {
-				match unsafe{ libc::write(self.$($fd_path)+, buf.as_ptr() as *const _, buf.len()) } {
-				    -1 if check_error() => {
-					return Err(io::Error::last_os_error());
-				    },
-				    -1 => continue,
-				    0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "write returned 0")),
+				match $crate::ffi::c_retry!(libc::write(self.$($fd_path)+, buf.as_ptr() as *const _, buf.len()) => -1) {
+				    -1 => return Err(io::Error::last_os_error()),
+				    0 => return Err(io::Error::new(io::ErrorKind::WriteZero, "write returned 0")),
 				    n => &buf[(n as usize)..],
 				}
 			    },
@@ -178,7 +163,7 @@ macro_rules! impl_io_for_fd {
 	    {
 		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
 		{
-		    match unsafe { libc::read(self.$($fd_path)+, buf.as_mut_ptr() as *mut _, buf.len()) } {
+		    match $crate::ffi::c_retry!(libc::read(self.$($fd_path)+, buf.as_mut_ptr() as *mut _, buf.len()) => -1) {
 			-1 => Err(io::Error::last_os_error()),
 			n => Ok(n as usize),
 		    }
@@ -189,11 +174,8 @@ macro_rules! impl_io_for_fd {
 			let n = match &mut buf {
 			    [] => break Ok(()),
 			    buf => {
-				match unsafe { libc::read(self.$($fd_path)+, (**buf).as_mut_ptr() as *mut libc::c_void, buf.len()) } {
-				    -1 if check_error() => {
-					return Err(io::Error::last_os_error());
-				    },
-				    -1 => continue,
+				match $crate::ffi::c_retry!(libc::read(self.$($fd_path)+, (**buf).as_mut_ptr() as *mut libc::c_void, buf.len()) => -1) {
+				    -1 => return Err(io::Error::last_os_error()),
 				    0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read returned 0")),
 				    n => n as usize,
 				}
@@ -0,0 +1,116 @@
+//! Passing file descriptors to other processes over Unix sockets (`SCM_RIGHTS`)
+use super::*;
+
+/// Send a duplicate of `fd` over the Unix socket `sock`, in an `SCM_RIGHTS` control message with a single byte of data.
+///
+/// This is how e.g. a sealed `memory::MemoryFile` is handed to a peer process, which receives it with `recv_fd()`. The file descriptor here is not closed.
+///
+/// # Returns
+/// If `sendmsg()` fails, then the error is returned (e.g. `ENOTSOCK` if `sock` is not a socket.)
+pub fn send_fd(sock: RawFd, fd: &(impl AsRawFd + ?Sized)) -> io::Result<()>
+{
+    let raw = fd.as_raw_fd();
+    let mut data = [0u8; 1];
+    let mut iov = libc::iovec {
+	iov_base: data.as_mut_ptr() as *mut _,
+	iov_len: data.len(),
+    };
+    // `u64`s keep the buffer aligned for `cmsghdr`.
+    let mut control = [0u64; 4];
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as usize;
+    debug_assert!(space <= mem::size_of_val(&control));
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut _;
+    msg.msg_controllen = space as _;
+    unsafe {
+	let cmsg = libc::CMSG_FIRSTHDR(&msg);
+	(*cmsg).cmsg_level = libc::SOL_SOCKET;
+	(*cmsg).cmsg_type = libc::SCM_RIGHTS;
+	(*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+	ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, raw);
+    }
+
+    match c_retry!(libc::sendmsg(sock, &msg, libc::MSG_NOSIGNAL) => -1) {
+	-1 => Err(io::Error::last_os_error()),
+	_ => Ok(()),
+    }
+}
+
+/// Receive a file descriptor sent with `send_fd()` over the Unix socket `sock`.
+///
+/// The received file descriptor is close-on-exec, and is closed when the returned `ManagedFD` is dropped.
+///
+/// # Returns
+/// If `recvmsg()` fails, then the error is returned.
+/// If the peer has closed the socket, an error of kind `UnexpectedEof` is returned.
+/// If the message did not carry exactly one file descriptor, an error of kind `InvalidData` is returned, and any received file descriptors are closed.
+pub fn recv_fd(sock: RawFd) -> io::Result<ManagedFD>
+{
+    let mut data = [0u8; 1];
+    let mut iov = libc::iovec {
+	iov_base: data.as_mut_ptr() as *mut _,
+	iov_len: data.len(),
+    };
+    let mut control = [0u64; 4];
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut _;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    match c_retry!(libc::recvmsg(sock, &mut msg, libc::MSG_CMSG_CLOEXEC) => -1) {
+	-1 => return Err(io::Error::last_os_error()),
+	0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Socket closed before a file descriptor was received")),
+	_ => (),
+    }
+
+    // Take ownership of everything received first, so nothing leaks on error.
+    let mut fds = Vec::new();
+    unsafe {
+	let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+	while !cmsg.is_null() {
+	    if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+		let data = libc::CMSG_DATA(cmsg);
+		let count = ((*cmsg).cmsg_len as usize - (data as usize - cmsg as usize)) / mem::size_of::<RawFd>();
+		for i in 0..count {
+		    fds.push(ManagedFD::from_raw_fd(ptr::read_unaligned((data as *const RawFd).add(i))));
+		}
+	    }
+	    cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+	}
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 || fds.len() != 1 {
+	return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected one file descriptor, received {}{}", fds.len(), if msg.msg_flags & libc::MSG_CTRUNC != 0 { " (truncated)" } else { "" })));
+    }
+    Ok(fds.remove(0))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn pass_memfd_over_socketpair()
+    {
+	let mut socks = [0; 2];
+	assert_eq!(unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0, socks.as_mut_ptr()) }, 0);
+	let (a, b) = unsafe { (ManagedFD::from_raw_fd(socks[0]), ManagedFD::from_raw_fd(socks[1])) };
+
+	let mut file = memory::MemoryFile::new().unwrap();
+	file.write_all(b"passed").unwrap();
+	send_fd(a.as_raw_fd(), &file).unwrap();
+
+	let received = unsafe { memory::MemoryFile::from_raw_fd(recv_fd(b.as_raw_fd()).unwrap().into_raw_fd()) };
+	assert_ne!(received.as_raw_fd(), file.as_raw_fd());
+	assert!(received.content_eq(&file).unwrap());
+
+	drop(a);
+	assert_eq!(recv_fd(b.as_raw_fd()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
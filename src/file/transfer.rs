@@ -0,0 +1,155 @@
+//! In-kernel transfers of data between file descriptors, without copying through userspace buffers.
+use super::*;
+
+/// Convert an optional offset into a pointer to an `off_t` for a C call, or null if there is none.
+#[inline]
+fn offset_ptr(offset: Option<u64>, storage: &mut libc::off_t) -> io::Result<*mut libc::off_t>
+{
+    Ok(match offset {
+	Some(offset) => {
+	    *storage = to_off_t(offset)?;
+	    storage as *mut _
+	},
+	None => ptr::null_mut(),
+    })
+}
+
+/// Copy up to `count` bytes from `inp` to `out` within the kernel via `sendfile()`.
+///
+/// If `offset` is `Some`, reading starts at that offset in `inp` and its file offset is left unchanged. Otherwise, reading starts at (and advances) the file offset of `inp`.
+///
+/// # Returns
+/// The number of bytes transferred, which may be less than `count` (and is 0 at the end of `inp`.)
+/// If `sendfile()` fails, the error is returned unchanged (e.g. `EINVAL` if `inp` does not support `mmap()`-like operations, as is the case for sockets.)
+pub fn sendfile(out: &(impl AsRawFd + ?Sized), inp: &(impl AsRawFd + ?Sized), offset: Option<u64>, count: usize) -> io::Result<usize>
+{
+    let mut off = 0;
+    let off = offset_ptr(offset, &mut off)?;
+    match unsafe { libc::sendfile(out.as_raw_fd(), inp.as_raw_fd(), off, count) } {
+	-1 => Err(io::Error::last_os_error()),
+	n => Ok(n as usize),
+    }
+}
+
+/// Copy up to `len` bytes from `src` to `dst` within the kernel via `copy_file_range()`. On supporting filesystems, this may share the data (a reflink) instead of copying it.
+///
+/// For each of `src_off` and `dst_off`: If it is `Some`, the copy uses that offset in the file and its file offset is left unchanged. Otherwise, the copy uses (and advances) the file offset.
+///
+/// # Returns
+/// The number of bytes copied, which may be less than `len` (and is 0 at the end of `src`.)
+/// If `copy_file_range()` fails, the error is returned unchanged. Notably, `ENOSYS` if the kernel does not support it, or `EXDEV` if the files are on filesystems it cannot copy between; in either case the caller can fall back to a userspace copy.
+pub fn copy_file_range(src: &(impl AsRawFd + ?Sized), src_off: Option<u64>, dst: &(impl AsRawFd + ?Sized), dst_off: Option<u64>, len: usize) -> io::Result<usize>
+{
+    let (mut soff, mut doff) = (0, 0);
+    let soff = offset_ptr(src_off, &mut soff)?;
+    let doff = offset_ptr(dst_off, &mut doff)?;
+    match unsafe { libc::copy_file_range(src.as_raw_fd(), soff, dst.as_raw_fd(), doff, len, 0) } {
+	-1 => Err(io::Error::last_os_error()),
+	n => Ok(n as usize),
+    }
+}
+
+c_flags! {
+    /// Flags for `splice()` and `tee()`, combined with `|`.
+    pub struct SpliceFlags: libc::c_uint {
+	/// Attempt to move pages instead of copying them. This is only a hint.
+	const MOVE = libc::SPLICE_F_MOVE;
+	/// Do not block on the pipe(s). The operation may still block on the other file descriptor.
+	const NONBLOCK = libc::SPLICE_F_NONBLOCK;
+	/// More data will be spliced in a subsequent call.
+	const MORE = libc::SPLICE_F_MORE;
+    }
+}
+
+/// Move up to `len` bytes from `inp` to `out` within the kernel via `splice()`.
+///
+/// At least one of `inp` and `out` **must** be a pipe; the other can be any file (e.g. a `MemoryFile` or regular file, or a socket.)
+/// The offset of a pipe end must be `None`. For a non-pipe end, `Some` uses that offset without changing its file offset, and `None` uses (and advances) its file offset.
+///
+/// # Returns
+/// The number of bytes moved, which may be less than `len` (and is 0 at the end of `inp`.)
+/// If `splice()` fails, the error is returned unchanged (e.g. `EINVAL` if neither end is a pipe, or `ESPIPE` if an offset is given for a pipe.)
+pub fn splice(inp: &(impl AsRawFd + ?Sized), inp_off: Option<u64>, out: &(impl AsRawFd + ?Sized), out_off: Option<u64>, len: usize, flags: SpliceFlags) -> io::Result<usize>
+{
+    let (mut ioff, mut ooff) = (0, 0);
+    let ioff = offset_ptr(inp_off, &mut ioff)?;
+    let ooff = offset_ptr(out_off, &mut ooff)?;
+    match unsafe { libc::splice(inp.as_raw_fd(), ioff, out.as_raw_fd(), ooff, len, flags.bits()) } {
+	-1 => Err(io::Error::last_os_error()),
+	n => Ok(n as usize),
+    }
+}
+
+/// Duplicate up to `len` bytes from the pipe `inp` into the pipe `out` via `tee()`, without consuming them from `inp`.
+///
+/// # Returns
+/// The number of bytes duplicated, which may be less than `len`.
+/// If `tee()` fails, the error is returned unchanged (e.g. `EINVAL` if either end is not a pipe.)
+pub fn tee(inp: &(impl AsRawFd + ?Sized), out: &(impl AsRawFd + ?Sized), len: usize, flags: SpliceFlags) -> io::Result<usize>
+{
+    match unsafe { libc::tee(inp.as_raw_fd(), out.as_raw_fd(), len, flags.bits()) } {
+	-1 => Err(io::Error::last_os_error()),
+	n => Ok(n as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn sendfile_between_memory_files()
+    {
+	let mut inp = memory::MemoryFile::new().unwrap();
+	inp.write_all(b"hello world").unwrap();
+	let out = memory::MemoryFile::new().unwrap();
+
+	assert_eq!(sendfile(&out, &inp, Some(6), 5).unwrap(), 5);
+	assert_eq!(sendfile(&out, &inp, Some(11), 5).unwrap(), 0);
+
+	let map = MappedFile::new(out, 5, Perm::Readonly, Flags::Shared).unwrap();
+	assert_eq!(&map[..], b"world");
+    }
+
+    #[test]
+    fn copy_file_range_and_deep_clone()
+    {
+	let mut src = memory::MemoryFile::new().unwrap();
+	src.write_all(b"hello world").unwrap();
+	let dst = memory::MemoryFile::new().unwrap();
+	assert_eq!(copy_file_range(&src, Some(0), &dst, Some(0), 5).unwrap(), 5);
+
+	let clone = src.deep_clone().unwrap();
+	src.write_all(b"!").unwrap();
+	let map = MappedFile::new(clone, 11, Perm::Readonly, Flags::Shared).unwrap();
+	assert_eq!(&map[..], b"hello world");
+	assert_eq!(&MappedFile::new(dst, 5, Perm::Readonly, Flags::Shared).unwrap()[..], b"hello");
+    }
+
+    #[test]
+    fn splice_and_tee_through_pipes()
+    {
+	let pipe = || {
+	    let mut fds = [0; 2];
+	    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+	    unsafe { (ManagedFD::from_raw_fd(fds[0]), ManagedFD::from_raw_fd(fds[1])) }
+	};
+	let (rx1, tx1) = pipe();
+	let (rx2, tx2) = pipe();
+
+	let mut src = memory::MemoryFile::new().unwrap();
+	src.write_all(b"hello world").unwrap();
+	assert_eq!(splice(&src, Some(0), &tx1, None, 11, SpliceFlags::empty()).unwrap(), 11);
+	assert_eq!(tee(&rx1, &tx2, 11, SpliceFlags::NONBLOCK).unwrap(), 11);
+
+	let (a, b) = (memory::MemoryFile::new().unwrap(), memory::MemoryFile::new().unwrap());
+	assert_eq!(splice(&rx1, None, &a, Some(0), 11, SpliceFlags::MOVE).unwrap(), 11);
+	assert_eq!(splice(&rx2, None, &b, Some(0), 11, SpliceFlags::MOVE | SpliceFlags::MORE).unwrap(), 11);
+	assert_eq!(&MappedFile::new(a, 11, Perm::Readonly, Flags::Shared).unwrap()[..], b"hello world");
+	assert_eq!(&MappedFile::new(b, 11, Perm::Readonly, Flags::Shared).unwrap()[..], b"hello world");
+
+	assert_eq!(splice(&src, Some(0), &src, Some(0), 1, SpliceFlags::empty()).unwrap_err().raw_os_error(), Some(libc::EINVAL));
+    }
+}
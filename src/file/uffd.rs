@@ -0,0 +1,262 @@
+//! Demand paging of mappings from userspace with `userfaultfd()`
+//!
+//! A range of a mapping is registered with a `UserFaultFd`. The first access to each missing page of it then blocks the faulting thread, until a handler supplies the page's contents with `UFFDIO_COPY`.
+//! This allows mappings whose contents are generated, decompressed, or fetched on demand.
+//!
+//! # Requirements
+//! * Linux 4.3 or later. Registered ranges must be private anonymous, shared memory (e.g. a shared `MemoryFile` mapping), or huge-page mappings.
+//! * Unless `/proc/sys/vm/unprivileged_userfaultfd` is `1`, the process needs `CAP_SYS_PTRACE`, or Linux 5.11 or later (where faults are only handled for user-mode accesses.)
+use super::*;
+
+/// Definitions from `<linux/userfaultfd.h>`, which `libc` does not export.
+mod sys
+{
+    pub const UFFD_API: u64 = 0xAA;
+    pub const UFFD_USER_MODE_ONLY: libc::c_int = 1;
+    pub const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+    pub const UFFDIO_REGISTER_MODE_MISSING: u64 = 1;
+
+    #[repr(C)]
+    pub struct uffdio_api
+    {
+	pub api: u64,
+	pub features: u64,
+	pub ioctls: u64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_range
+    {
+	pub start: u64,
+	pub len: u64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_register
+    {
+	pub range: uffdio_range,
+	pub mode: u64,
+	pub ioctls: u64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_copy
+    {
+	pub dst: u64,
+	pub src: u64,
+	pub len: u64,
+	pub mode: u64,
+	pub copy: i64,
+    }
+
+    /// `struct uffd_msg`, with the `arg` union as its `pagefault` member.
+    #[repr(C)]
+    pub struct uffd_msg
+    {
+	pub event: u8,
+	pub reserved1: u8,
+	pub reserved2: u16,
+	pub reserved3: u32,
+	pub flags: u64,
+	pub address: u64,
+	pub feat: u64,
+    }
+
+    /// The generic `_IOC()` encoding.
+    const fn ioc(dir: u64, nr: u64, size: usize) -> libc::c_ulong
+    {
+	((dir << 30) | ((size as u64) << 16) | (UFFD_API << 8) | nr) as libc::c_ulong
+    }
+    const WRITE: u64 = 1;
+    const READ: u64 = 2;
+
+    pub const UFFDIO_API: libc::c_ulong = ioc(READ | WRITE, 0x3F, std::mem::size_of::<uffdio_api>());
+    pub const UFFDIO_REGISTER: libc::c_ulong = ioc(READ | WRITE, 0x00, std::mem::size_of::<uffdio_register>());
+    pub const UFFDIO_UNREGISTER: libc::c_ulong = ioc(READ, 0x01, std::mem::size_of::<uffdio_range>());
+    pub const UFFDIO_COPY: libc::c_ulong = ioc(READ | WRITE, 0x03, std::mem::size_of::<uffdio_copy>());
+}
+
+/// A `userfaultfd()` file descriptor, which resolves page faults in the ranges registered with it. See the module documentation.
+#[derive(Debug)]
+pub struct UserFaultFd(ManagedFD);
+
+impl UserFaultFd
+{
+    /// Create a new `userfaultfd()`, and negotiate the API with the kernel.
+    ///
+    /// Faults from user-mode accesses only are requested where the kernel supports it, so this works without privileges on Linux 5.11 and later.
+    ///
+    /// # Returns
+    /// If `userfaultfd()` fails (e.g. `ENOSYS` if unsupported, or `EPERM` if not permitted), or the API handshake fails, then the error is returned.
+    pub fn new() -> io::Result<Self>
+    {
+	let create = |flags: libc::c_int| match unsafe { libc::syscall(libc::SYS_userfaultfd, libc::O_CLOEXEC | flags) } {
+	    -1 => Err(io::Error::last_os_error()),
+	    fd => Ok(unsafe { ManagedFD::from_raw_fd(fd as RawFd) }),
+	};
+	let fd = match create(sys::UFFD_USER_MODE_ONLY) {
+	    // Kernels before 5.11 do not know the flag.
+	    Err(e) if e.raw_os_error() == Some(libc::EINVAL) => create(0),
+	    r => r,
+	}?;
+	let mut api = sys::uffdio_api {
+	    api: sys::UFFD_API,
+	    features: 0,
+	    ioctls: 0,
+	};
+	if unsafe { libc::ioctl(fd.as_raw_fd(), sys::UFFDIO_API, &mut api) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	Ok(Self(fd))
+    }
+
+    /// Resolve `range` of `map` into a whole-page range for an ioctl.
+    fn page_range<T>(map: &MappedFile<T>, range: impl ops::RangeBounds<usize>) -> sys::uffdio_range
+    {
+	let (addr, len) = map.raw_page_range(range);
+	sys::uffdio_range {
+	    start: addr as u64,
+	    len: round_up_to_page(len) as u64,
+	}
+    }
+
+    /// Register the pages covering `range` of `map` for handling missing-page faults.
+    ///
+    /// The registration lasts until `unregister()` is called, or the pages are unmapped.
+    ///
+    /// # Returns
+    /// If `UFFDIO_REGISTER` fails (e.g. `EINVAL` for an unsupported kind of mapping), then the error is returned.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    pub fn register<T>(&self, map: &MappedFile<T>, range: impl ops::RangeBounds<usize>) -> io::Result<()>
+    {
+	let mut reg = sys::uffdio_register {
+	    range: Self::page_range(map, range),
+	    mode: sys::UFFDIO_REGISTER_MODE_MISSING,
+	    ioctls: 0,
+	};
+	if unsafe { libc::ioctl(self.0.as_raw_fd(), sys::UFFDIO_REGISTER, &mut reg) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	Ok(())
+    }
+
+    /// Stop handling faults for the pages covering `range` of `map`. Pages that have not been supplied are then zero-filled (or read from the file) on access, as usual.
+    ///
+    /// # Returns
+    /// If `UFFDIO_UNREGISTER` fails, then the error is returned.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    pub fn unregister<T>(&self, map: &MappedFile<T>, range: impl ops::RangeBounds<usize>) -> io::Result<()>
+    {
+	let mut range = Self::page_range(map, range);
+	if unsafe { libc::ioctl(self.0.as_raw_fd(), sys::UFFDIO_UNREGISTER, &mut range) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	Ok(())
+    }
+
+    /// Wait for a page fault in a registered range, and resolve it with the contents returned by `supply`.
+    ///
+    /// `supply` is called with the page-aligned address of the faulting page, and returns its contents. If these are shorter than a page, the rest of the page is zero-filled; anything past a page is ignored.
+    /// The contents are copied into place atomically with `UFFDIO_COPY`, which wakes the faulting thread.
+    ///
+    /// # Returns
+    /// The page-aligned address of the page that was supplied.
+    /// If reading the fault or `UFFDIO_COPY` fails, then the error is returned. A page that was already supplied (`EEXIST`) is not an error.
+    pub fn handle_fault<F, R>(&self, mut supply: F) -> io::Result<usize>
+    where F: FnMut(usize) -> R,
+	  R: AsRef<[u8]>
+    {
+	let page = get_page_size();
+	let address = loop {
+	    let mut msg = mem::MaybeUninit::<sys::uffd_msg>::uninit();
+	    match c_retry!(libc::read(self.0.as_raw_fd(), msg.as_mut_ptr() as *mut _, mem::size_of::<sys::uffd_msg>()) => -1) {
+		-1 => return Err(io::Error::last_os_error()),
+		n if n as usize != mem::size_of::<sys::uffd_msg>() => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Short read of userfaultfd message")),
+		_ => (),
+	    }
+	    let msg = unsafe { msg.assume_init() };
+	    // No other events are enabled, but skip any that are not faults.
+	    if msg.event == sys::UFFD_EVENT_PAGEFAULT {
+		break (msg.address as usize) & !(page - 1);
+	    }
+	};
+
+	let mut buf = vec![0u8; page];
+	let data = supply(address);
+	let data = data.as_ref();
+	let n = data.len().min(page);
+	buf[..n].copy_from_slice(&data[..n]);
+
+	let mut copy = sys::uffdio_copy {
+	    dst: address as u64,
+	    src: buf.as_ptr() as u64,
+	    len: page as u64,
+	    mode: 0,
+	    copy: 0,
+	};
+	if unsafe { libc::ioctl(self.0.as_raw_fd(), sys::UFFDIO_COPY, &mut copy) } != 0 {
+	    let err = io::Error::last_os_error();
+	    if err.raw_os_error() != Some(libc::EEXIST) {
+		return Err(err);
+	    }
+	}
+	Ok(address)
+    }
+
+    /// Resolve page faults with `supply` (see `handle_fault()`) until an error occurs. This is usually run on a dedicated thread.
+    ///
+    /// # Returns
+    /// The error that stopped the loop.
+    pub fn handle<F, R>(&self, mut supply: F) -> io::Error
+    where F: FnMut(usize) -> R,
+	  R: AsRef<[u8]>
+    {
+	loop {
+	    if let Err(e) = self.handle_fault(&mut supply) {
+		return e;
+	    }
+	}
+    }
+}
+
+impl AsRawFd for UserFaultFd
+{
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+	self.0.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn supply_pages_on_fault()
+    {
+	let uffd = match UserFaultFd::new() {
+	    Ok(uffd) => uffd,
+	    // Not supported or permitted here.
+	    Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EPERM)) => return,
+	    Err(e) => panic!("userfaultfd(): {e}"),
+	};
+	let page = get_page_size();
+	let map = MappedFile::anonymous_from(Anonymous, page * 3, Perm::ReadWrite).unwrap();
+	uffd.register(&map, ..).unwrap();
+
+	let base = map.address();
+	let handler = std::thread::spawn(move || {
+	    (0..2).map(|_| uffd.handle_fault(|addr| vec![((addr - base) / page) as u8 + 1; 16]).unwrap()).collect::<Vec<_>>()
+	});
+	assert_eq!(map[page * 2], 3);
+	assert_eq!(&map[..16], &[1; 16]);
+	// The rest of a supplied page is zero-filled.
+	assert_eq!(map[16], 0);
+	assert_eq!(handler.join().unwrap(), vec![base + page * 2, base]);
+    }
+}
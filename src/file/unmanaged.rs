@@ -63,6 +63,16 @@ impl From<UnmanagedFD> for RawFd
 
 // No impl for `IntoRawFd` because `UnmanagedFD` is not owning
 
+impl<'a> From<std::os::fd::BorrowedFd<'a>> for UnmanagedFD
+{
+    /// Alias a borrowed fd. The returned value does not carry the borrow's lifetime, so it must not be used after the fd is closed.
+    #[inline]
+    fn from(from: std::os::fd::BorrowedFd<'a>) -> Self
+    {
+	Self::new(&from)
+    }
+}
+
 impl FromRawFd for UnmanagedFD
 {
     unsafe fn from_raw_fd(fd: RawFd) -> Self {
@@ -79,6 +89,9 @@ impl AsRawFd for UnmanagedFD
     }
 }
 
+// Reading and writing through an alias never closes the fd.
+raw::impl_io_for_fd!(UnmanagedFD => .0.get());
+
 impl From<UnmanagedFD> for ManagedFD {
     #[inline]
     fn from(from: UnmanagedFD) -> Self {
@@ -88,3 +101,36 @@ impl From<UnmanagedFD> for ManagedFD {
 }
 
 //TODO: implement a full version of the temporary struct `UnmanagedFD` from `utf8encode`
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn from_borrowed_fd()
+    {
+	use std::os::fd::{AsFd, OwnedFd};
+	let owned = OwnedFd::from(ManagedFD::from(memory::MemoryFile::new().unwrap()));
+	let alias = UnmanagedFD::from(owned.as_fd());
+	assert_eq!(alias.get(), owned.as_raw_fd());
+    }
+
+    #[test]
+    fn read_write_pipe()
+    {
+	let mut fds = [0; 2];
+	assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+	let (rx, tx) = unsafe { (ManagedFD::from_raw_fd(fds[0]), ManagedFD::from_raw_fd(fds[1])) };
+
+	UnmanagedFD::new(&tx).write_all(b"through an alias").unwrap();
+	drop(tx);
+
+	let mut out = String::new();
+	UnmanagedFD::new(&rx).read_to_string(&mut out).unwrap();
+	assert_eq!(out, "through an alias");
+	// The aliases did not close `rx`.
+	assert_ne!(unsafe { libc::fcntl(rx.as_raw_fd(), libc::F_GETFD) }, -1);
+    }
+}
@@ -1,6 +1,7 @@
 //! All flags for controlling a `MappedFile<T>`.
 use super::*;
 use libc::c_int;
+use std::str::FromStr;
 
 /// Permissions for the mapped pages.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Default)]
@@ -187,10 +188,82 @@ pub enum Advice {
     Normal,
     Sequential,
     RandomAccess,
+    /// Exclude the mapping from core dumps.
+    ///
+    /// This is a lightweight hardening step for mappings holding secrets or key material (which should also be kept out of swap, e.g. by locking it), and keeps very large mappings from bloating dumps.
+    DontDump,
+    /// Undo `DontDump`, including the mapping in core dumps again.
+    DoDump,
+    /// Zero the mapping in the child process after a `fork()`, so its contents are not inherited.
+    ///
+    /// This only applies to private anonymous mappings; `madvise()` fails with `EINVAL` for any other mapping.
+    WipeOnFork,
+    /// Undo `WipeOnFork`, letting the child inherit the mapping's contents again.
+    KeepOnFork,
+    /// Do not make the mapping available to the child process at all after a `fork()`: The region is unmapped in the child, and accessing it there raises `SIGSEGV`.
+    ///
+    /// This is stronger than `WipeOnFork`, and is not restricted to private anonymous mappings. It keeps e.g. secrets or DMA buffers from being shared with (or copied-on-write into) a child by accident.
+    ///
+    /// # Note
+    /// For a *private* mapping, the child would otherwise get a copy-on-write snapshot of the contents; for a *shared* one, it would share the memory. This removes both.
+    DontFork,
+    /// Undo `DontFork`, letting the child inherit the mapping again.
+    DoFork,
+    /// Opt the mapping into Kernel Samepage Merging (KSM), which deduplicates pages with identical contents across processes and mappings.
+    ///
+    /// This only has an effect when KSM is enabled system-wide (by writing `1` to `/sys/kernel/mm/ksm/run`), and only applies to private anonymous mappings: Other mappings are silently ignored.
+    /// Merged pages are copied again on the next write to them.
+    ///
+    /// # Note
+    /// If the kernel is built without KSM support, `madvise()` fails with `EINVAL`.
+    Mergeable,
+    /// Undo `Mergeable`, un-merging any pages KSM has merged in the mapping.
+    Unmergeable,
 }
 
 impl Advice
 {
+    /// Every variant, in the order they are applied by `AdviceSet`.
+    const ALL: &[Self] = &[
+	Self::Normal,
+	Self::Sequential,
+	Self::RandomAccess,
+	Self::DontDump,
+	Self::DoDump,
+	Self::WipeOnFork,
+	Self::KeepOnFork,
+	Self::DontFork,
+	Self::DoFork,
+	Self::Mergeable,
+	Self::Unmergeable,
+    ];
+
+    /// Advices that each set the access pattern of the mapping, and are therefore mutually exclusive.
+    const ACCESS_PATTERNS: AdviceSet = AdviceSet::empty()
+	.with(Self::Normal)
+	.with(Self::Sequential)
+	.with(Self::RandomAccess);
+
+    /// Advices that are mutually exclusive with each other, but not with the access patterns.
+    const DUMP: AdviceSet = AdviceSet::empty()
+	.with(Self::DontDump)
+	.with(Self::DoDump);
+
+    /// Advices controlling the inheritance of the mapping's contents on `fork()`, which are also mutually exclusive.
+    const FORK: AdviceSet = AdviceSet::empty()
+	.with(Self::WipeOnFork)
+	.with(Self::KeepOnFork);
+
+    /// Advices controlling whether the mapping is inherited at all on `fork()`, which are also mutually exclusive.
+    const INHERIT: AdviceSet = AdviceSet::empty()
+	.with(Self::DontFork)
+	.with(Self::DoFork);
+
+    /// Advices opting the mapping in or out of KSM, which are also mutually exclusive.
+    const MERGE: AdviceSet = AdviceSet::empty()
+	.with(Self::Mergeable)
+	.with(Self::Unmergeable);
+
 #[inline(always)]
     pub(crate) const fn get_madv(self) -> c_int
     {
@@ -198,11 +271,291 @@ impl Advice
             MADV_NORMAL,
             MADV_SEQUENTIAL,
             MADV_RANDOM,
+            MADV_DONTDUMP,
+            MADV_DODUMP,
+            MADV_WIPEONFORK,
+            MADV_KEEPONFORK,
+            MADV_DONTFORK,
+            MADV_DOFORK,
+            MADV_MERGEABLE,
+            MADV_UNMERGEABLE,
         };
         match self {
             Self::Normal => MADV_NORMAL,
             Self::Sequential => MADV_SEQUENTIAL,
             Self::RandomAccess => MADV_RANDOM,
+            Self::DontDump => MADV_DONTDUMP,
+            Self::DoDump => MADV_DODUMP,
+            Self::WipeOnFork => MADV_WIPEONFORK,
+            Self::KeepOnFork => MADV_KEEPONFORK,
+            Self::DontFork => MADV_DONTFORK,
+            Self::DoFork => MADV_DOFORK,
+            Self::Mergeable => MADV_MERGEABLE,
+            Self::Unmergeable => MADV_UNMERGEABLE,
+        }
+    }
+}
+
+/// A set of `Advice` values to apply to a mapping at once, built from `Advice` values with `|`.
+///
+/// The kernel's advice values are not bit-flags, so they cannot be combined into a single `madvise()` mask. Instead, each advice in the set is applied with its own `madvise()` call, in the order the variants of `Advice` are declared.
+///
+/// # Legal combinations
+/// `Normal`, `Sequential`, and `RandomAccess` each set the access pattern of the mapping, so they are mutually exclusive; a set containing more than one of them is rejected by `MappedFile::advise()`.
+/// Likewise, `DontDump` and `DoDump` undo each other and cannot be combined, nor can `WipeOnFork` and `KeepOnFork`, `DontFork` and `DoFork`, or `Mergeable` and `Unmergeable`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Default)]
+#[repr(transparent)]
+pub struct AdviceSet(u32);
+
+impl AdviceSet
+{
+    /// A set containing no advice.
+    #[inline]
+    pub const fn empty() -> Self
+    {
+	Self(0)
+    }
+
+    #[inline(always)]
+    const fn bit(adv: Advice) -> u32
+    {
+	1 << (adv as u32)
+    }
+
+    /// Add `adv` to this set.
+    #[inline]
+    pub const fn with(self, adv: Advice) -> Self
+    {
+	Self(self.0 | Self::bit(adv))
+    }
+
+    /// Check if `adv` is in this set.
+    #[inline]
+    pub const fn contains(&self, adv: Advice) -> bool
+    {
+	(self.0 & Self::bit(adv)) != 0
+    }
+
+    /// Check if this set contains no advice.
+    #[inline]
+    pub const fn is_empty(&self) -> bool
+    {
+	self.0 == 0
+    }
+
+    /// Check if this set contains no mutually exclusive advices. (See the *Legal combinations* section above.)
+    #[inline]
+    pub const fn is_valid(&self) -> bool
+    {
+	(self.0 & Advice::ACCESS_PATTERNS.0).count_ones() <= 1
+	    && (self.0 & Advice::DUMP.0).count_ones() <= 1
+	    && (self.0 & Advice::FORK.0).count_ones() <= 1
+	    && (self.0 & Advice::INHERIT.0).count_ones() <= 1
+	    && (self.0 & Advice::MERGE.0).count_ones() <= 1
+    }
+
+    /// An iterator over each advice in this set, in the order they are applied.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Advice> + '_
+    {
+	Advice::ALL.iter().copied().filter(|&adv| self.contains(adv))
+    }
+}
+
+impl From<Advice> for AdviceSet
+{
+    #[inline]
+    fn from(from: Advice) -> Self
+    {
+	Self::empty().with(from)
+    }
+}
+
+impl FromIterator<Advice> for AdviceSet
+{
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = Advice>>(iter: I) -> Self
+    {
+	iter.into_iter().fold(Self::empty(), Self::with)
+    }
+}
+
+impl ops::BitOr for Advice
+{
+    type Output = AdviceSet;
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self::Output
+    {
+	AdviceSet::from(self).with(rhs)
+    }
+}
+
+impl ops::BitOr<Advice> for AdviceSet
+{
+    type Output = Self;
+    #[inline]
+    fn bitor(self, rhs: Advice) -> Self::Output
+    {
+	self.with(rhs)
+    }
+}
+
+impl ops::BitOr for AdviceSet
+{
+    type Output = Self;
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self::Output
+    {
+	Self(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign<Advice> for AdviceSet
+{
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Advice)
+    {
+	*self = self.with(rhs);
+    }
+}
+
+/// NUMA memory policy for the pages of a mapping. These will control `mbind()`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Default)]
+pub enum NumaPolicy
+{
+    /// Remove any policy from the mapping, falling back to the policy of the thread that faults the pages in. The node set must be empty.
+#[default]
+    Default,
+    /// Allocate pages only on the given nodes.
+    Bind,
+    /// Allocate pages round-robin across the given nodes.
+    Interleave,
+    /// Allocate pages on the given node if possible, falling back to others. An empty node set means the node of the faulting CPU.
+    Preferred,
+}
+
+impl NumaPolicy
+{
+#[inline(always)]
+    pub(crate) const fn get_mpol(self) -> c_int
+    {
+        use libc::{
+            MPOL_DEFAULT,
+            MPOL_BIND,
+            MPOL_INTERLEAVE,
+            MPOL_PREFERRED,
+        };
+        match self {
+            Self::Default => MPOL_DEFAULT,
+            Self::Bind => MPOL_BIND,
+            Self::Interleave => MPOL_INTERLEAVE,
+            Self::Preferred => MPOL_PREFERRED,
         }
     }
 }
+
+/// Error returned when parsing a `Perm`, `Flags`, or `Flush` from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseFlagError
+{
+    /// The name of the type being parsed.
+    pub kind: &'static str,
+    /// The string that failed to parse.
+    pub input: String,
+    /// The accepted strings.
+    pub expected: &'static [&'static str],
+}
+
+impl error::Error for ParseFlagError{}
+
+impl fmt::Display for ParseFlagError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	write!(f, "invalid {} {:?}: expected one of {}", self.kind, self.input, self.expected.join(", "))
+    }
+}
+
+/// Implements `FromStr` (case-insensitive) and `Display` for a fieldless enum from a table of names. The first name of each variant is the one displayed.
+macro_rules! impl_flag_strings {
+    ($type:ident: $kind:literal { $($variant:ident => $name:literal $(| $alias:literal)*),+ $(,)? }) => {
+	impl $type
+	{
+	    const NAMES: &[&str] = &[$($name $(, $alias)*),+];
+	}
+
+	impl FromStr for $type
+	{
+	    type Err = ParseFlagError;
+
+	    fn from_str(s: &str) -> Result<Self, Self::Err>
+	    {
+		$(
+		    if s.eq_ignore_ascii_case($name) $(|| s.eq_ignore_ascii_case($alias))* {
+			return Ok(Self::$variant);
+		    }
+		)+
+		Err(ParseFlagError {
+		    kind: $kind,
+		    input: s.to_owned(),
+		    expected: Self::NAMES,
+		})
+	    }
+	}
+
+	impl fmt::Display for $type
+	{
+	    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	    {
+		f.write_str(match self {
+		    $(Self::$variant => $name),+
+		})
+	    }
+	}
+    };
+}
+
+impl_flag_strings!(Perm: "permissions" {
+    ReadWrite => "rw" | "wr",
+    Readonly => "r",
+    Writeonly => "w",
+    RX => "rx" | "xr",
+    WRX => "wrx" | "rwx",
+});
+
+impl_flag_strings!(Flags: "mapping flags" {
+    Shared => "shared",
+    Private => "private",
+});
+
+impl_flag_strings!(Flush: "flush mode" {
+    Wait => "sync" | "wait",
+    Async => "async",
+    Invalidate => "invalidate",
+    InvalidateAsync => "invalidate-async",
+});
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_display_round_trip()
+    {
+	fn round_trip<T: FromStr<Err = ParseFlagError> + fmt::Display + fmt::Debug + PartialEq>(values: &[T])
+	{
+	    for value in values {
+		assert_eq!(&value.to_string().parse::<T>().unwrap(), value);
+	    }
+	}
+	round_trip(&[Perm::ReadWrite, Perm::Readonly, Perm::Writeonly, Perm::RX, Perm::WRX]);
+	round_trip(&[Flags::Shared, Flags::Private]);
+	round_trip(&[Flush::Wait, Flush::Async, Flush::Invalidate, Flush::InvalidateAsync]);
+
+	assert_eq!("RWX".parse(), Ok(Perm::WRX));
+	let error = "public".parse::<Flags>().unwrap_err();
+	assert_eq!(error.input, "public");
+	assert_eq!(error.to_string(), r#"invalid mapping flags "public": expected one of shared, private"#);
+    }
+}
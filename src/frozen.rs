@@ -0,0 +1,86 @@
+//! Read-only views of mappings. See `MappedFile::freeze()`.
+use super::*;
+
+/// A mapping that has been made read-only, exposing only its immutable accessors.
+///
+/// Created by `MappedFile::freeze()`, which has already `mprotect()`ed the memory to `PROT_READ`. There is no `DerefMut` or `as_slice_mut()`, so the frozen memory cannot be written to without going through `unsafe` code (which would then fault with `SIGSEGV`.)
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct ReadOnly<M>(M);
+
+impl<T> MappedFile<T>
+{
+    /// Make the whole mapping read-only with `mprotect(PROT_READ)`, and return it wrapped so it can no longer be written to.
+    ///
+    /// # Returns
+    /// If `mprotect()` fails, then the error is returned along with the unchanged mapping.
+    #[inline]
+    pub fn freeze(mut self) -> Result<ReadOnly<Self>, TryNewError<Self>>
+    {
+	match self.protect(Perm::Readonly) {
+	    Ok(()) => Ok(ReadOnly(self)),
+	    Err(e) => Err(TryNewError::wrap((e, self))),
+	}
+    }
+}
+
+impl<M> ReadOnly<M>
+{
+    /// Unwrap the frozen mapping.
+    ///
+    /// # Note
+    /// The memory is still protected as `PROT_READ`: Writing to it faults until it is made writable again with `MappedFile::protect()`.
+    #[inline(always)]
+    pub fn into_inner(self) -> M
+    {
+	self.0
+    }
+}
+
+impl<T> ops::Deref for ReadOnly<MappedFile<T>>
+{
+    type Target = MappedFile<T>;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target
+    {
+	&self.0
+    }
+}
+
+impl<T> AsRef<[u8]> for ReadOnly<MappedFile<T>>
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8]
+    {
+	self.0.as_slice()
+    }
+}
+
+impl<T> Borrow<[u8]> for ReadOnly<MappedFile<T>>
+{
+    #[inline]
+    fn borrow(&self) -> &[u8]
+    {
+	self.0.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn freeze_is_read_only()
+    {
+	let page = get_page_size();
+	let mut map = MappedFile::shared_anonymous(page, Perm::ReadWrite).unwrap();
+	map[..6].copy_from_slice(b"frozen");
+	let frozen = map.freeze().unwrap();
+	assert_eq!(&frozen[..6], b"frozen");
+
+	// The region must now be mapped without write access.
+	let entry = file::maps::find_mapping(frozen.address()).unwrap().expect("mapping not listed");
+	assert!(entry.perms.read && !entry.perms.write && !entry.perms.execute, "{}", entry.perms);
+    }
+}
@@ -7,6 +7,10 @@ use std::{
     fs,
     path::{Path, PathBuf},
     fmt, error,
+    sync::{
+	Arc,
+	RwLock,
+    },
 };
 use libc::{
     c_int,
@@ -16,6 +20,9 @@ use libc::{
 /// Location in which the kernel exposes available huge-page sizes.
 pub const HUGEPAGE_LOCATION: &'static str = "/sys/kernel/mm/hugepages/";
 
+/// Location in which the kernel exposes each NUMA node (and its per-node huge-page pools.)
+pub const NUMA_NODE_LOCATION: &str = "/sys/devices/system/node/";
+
 /// Represents a statically defined `MAP_HUGE_*` flag.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Copy)]
 #[repr(transparent)]
@@ -54,6 +61,9 @@ impl Default for MapHugeFlag
 	Self(MAP_HUGE_SHIFT)
     }
 }
+/// `log2(1024)`: The shift between a size in kB and in bytes.
+const KB_SHIFT: usize = 10;
+
 #[inline(always)]
 const fn log2(n: usize) -> usize
 {
@@ -80,10 +90,12 @@ impl MapHugeFlag
     pub const HUGE_1GB: Self = Self(libc::MAP_HUGE_1GB);
     
     /// Calculate a `MAP_HUGE_*` flag from a size (in kB).
+    ///
+    /// The flag encodes the base-2 logarithm of the page size *in bytes*.
     #[inline(always)] 
     pub const fn calculate(kilobytes: NonZeroUsize) -> Self
     {
-	Self((log2(kilobytes.get()) << (MAP_HUGE_SHIFT as usize)) as c_int)
+	Self(((log2(kilobytes.get()) + KB_SHIFT) << (MAP_HUGE_SHIFT as usize)) as c_int)
     }
 
     /// Attempt to calculate `MAP_HUGE_*` flag from a size (in kB).
@@ -93,7 +105,7 @@ impl MapHugeFlag
 	match kilobytes {
 	    0 => None,
 	    kilobytes => {
-		if let Some(shift) = log2(kilobytes).checked_shl(MAP_HUGE_SHIFT as u32) {
+		if let Some(shift) = (log2(kilobytes) + KB_SHIFT).checked_shl(MAP_HUGE_SHIFT as u32) {
 		    if shift <= c_int::MAX as usize {
 			return Some(Self(shift as c_int));
 		    }
@@ -129,6 +141,176 @@ impl MapHugeFlag
     {
 	self.0
     }
+
+    /// The huge-page size (in bytes) encoded in this flag.
+    ///
+    /// # Returns
+    /// `None` for `HUGE_DEFAULT`, which does not encode a size, or if the encoded size does not fit in a `usize`.
+    #[inline]
+    pub const fn size_bytes(&self) -> Option<usize>
+    {
+	if self.is_default() {
+	    None
+	} else {
+	    1usize.checked_shl((self.0 >> MAP_HUGE_SHIFT) as u32)
+	}
+    }
+
+    /// Extract the huge-page flag from a full set of `mmap()` flags.
+    ///
+    /// # Returns
+    /// `None` if `MAP_HUGETLB` is not set in `flags`. If it is set without a `MAP_HUGE_*` size, `HUGE_DEFAULT` is returned.
+    #[inline]
+    pub(crate) const fn from_mmap_flags(flags: c_int) -> Option<Self>
+    {
+	/// The bits of the huge-page size, above `MAP_HUGE_SHIFT`.
+	const MAP_HUGE_MASK: c_int = 0x3f;
+	if flags & libc::MAP_HUGETLB == 0 {
+	    return None;
+	}
+	match flags & (MAP_HUGE_MASK << MAP_HUGE_SHIFT) {
+	    0 => Some(Self::HUGE_DEFAULT),
+	    mask => Some(Self(mask)),
+	}
+    }
+
+    /// The huge-page size (in bytes) this flag represents.
+    ///
+    /// For `HUGE_DEFAULT`, this is the smallest huge-page size on the system, if it can be determined.
+    pub(crate) fn page_size(self) -> Option<usize>
+    {
+	if self.is_default() {
+	    cached_hugepage_sizes().as_ref().as_ref().ok()?.first()?.checked_mul(1024)
+	} else {
+	    self.size_bytes()
+	}
+    }
+}
+
+impl fmt::Display for MapHugeFlag
+{
+    /// Format the decoded huge-page size human-readably (e.g. `2MB`), or `default` for `HUGE_DEFAULT`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	match self.size_bytes() {
+	    None if self.is_default() => f.write_str("default"),
+	    Some(bytes) if bytes >= 1 << KB_SHIFT => fmt_kilobytes(bytes >> KB_SHIFT, f),
+	    Some(bytes) => write!(f, "{bytes}B"),
+	    None => write!(f, "<invalid huge-page mask {:#x}>", self.0),
+	}
+    }
+}
+
+/// Round `n` up to the next multiple of the huge-page size represented by `huge`.
+///
+/// # Returns
+/// If the huge-page size cannot be determined (see `MapHugeFlag::HUGE_DEFAULT`), or the rounded value overflows, `None` is returned.
+#[inline]
+pub fn round_up_to_hugepage(n: usize, huge: MapHugeFlag) -> Option<usize>
+{
+    n.checked_next_multiple_of(huge.page_size()?)
+}
+
+/// Round `n` down to the previous multiple of the huge-page size represented by `huge`.
+///
+/// # Returns
+/// If the huge-page size cannot be determined (see `MapHugeFlag::HUGE_DEFAULT`), `None` is returned.
+#[inline]
+pub fn round_down_to_hugepage(n: usize, huge: MapHugeFlag) -> Option<usize>
+{
+    let size = huge.page_size()?;
+    Some(n - (n % size))
+}
+
+/// Error for when a requested huge-page size is not available on the system.
+///
+/// See `MappedFile::try_new_hugetlb_checked()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HugePageUnavailable
+{
+    /// The requested huge-page size (in kB.)
+    pub requested_kb: usize,
+    /// The huge-page sizes (in kB) available on the system, sorted.
+    pub available_kb: Vec<usize>,
+}
+
+impl error::Error for HugePageUnavailable{}
+impl fmt::Display for HugePageUnavailable
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	struct Kb(usize);
+	impl fmt::Display for Kb
+	{
+	    #[inline]
+	    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	    {
+		fmt_kilobytes(self.0, f)
+	    }
+	}
+
+	write!(f, "huge-page size {} is not available on this system (available: ", Kb(self.requested_kb))?;
+	if self.available_kb.is_empty() {
+	    f.write_str("none")?;
+	}
+	for (i, &size) in self.available_kb.iter().enumerate() {
+	    if i != 0 {
+		f.write_str(", ")?;
+	    }
+	    write!(f, "{}", Kb(size))?;
+	}
+	f.write_str(")")
+    }
+}
+
+impl From<HugePageUnavailable> for io::Error
+{
+    #[inline]
+    fn from(from: HugePageUnavailable) -> Self
+    {
+	io::Error::new(io::ErrorKind::Unsupported, from)
+    }
+}
+
+impl From<HugePageCalcErr> for io::Error
+{
+    #[inline]
+    fn from(from: HugePageCalcErr) -> Self
+    {
+	io::Error::new(io::ErrorKind::InvalidInput, from)
+    }
+}
+
+impl MapHugeFlag
+{
+    /// Check that the huge-page size this flag represents is available on the system.
+    ///
+    /// # Returns
+    /// If the system's huge-page sizes cannot be determined, then that error is returned.
+    /// If the size is not one of them, a `HugePageUnavailable` error is returned.
+    pub fn check_available(self) -> io::Result<()>
+    {
+	let available = system_hugepage_sizes()?;
+	let requested_kb = self.page_size().map(|bytes| bytes >> KB_SHIFT);
+	match requested_kb {
+	    Some(kb) if available.contains(&kb) => Ok(()),
+	    requested_kb => Err(HugePageUnavailable {
+		requested_kb: requested_kb.unwrap_or(0),
+		available_kb: available.to_vec(),
+	    }.into()),
+	}
+    }
+
+    /// The bits to add to `mmap()`'s flags to map with this huge-page size: `MAP_HUGETLB`, and the encoded size (if it is not `HUGE_DEFAULT`.)
+    #[inline]
+    pub(crate) const fn mmap_flags(self) -> c_int
+    {
+	if self.is_default() {
+	    libc::MAP_HUGETLB
+	} else {
+	    libc::MAP_HUGETLB | self.0
+	}
+    }
 }
 
 impl From<MapHugeFlag> for c_int
@@ -243,7 +425,8 @@ impl HugePage
 		fn compute_selected(func: for<'r> fn (&'r [usize]) -> Option<&'r usize>) -> Option<MapHugeFlag>
 		{
 		    use std::borrow::Cow;
-		    let mask = match SYSTEM_HUGEPAGE_SIZES.as_ref() {
+		    let cached = cached_hugepage_sizes();
+		    let mask = match cached.as_ref() {
 			Ok(avail) => Cow::Borrowed(&avail[..]),
 			Err(_) => {
 			    // Attempt to re-scan the system. Fail if scan fails.
@@ -274,23 +457,167 @@ impl HugePage
     }
 }
 
-lazy_static! {
-    /// A persistent invocation of `scan_hugepages()`.
-    pub(crate) static ref SYSTEM_HUGEPAGE_SIZES: io::Result<Vec<usize>> = {
-	let mut val: io::Result<Vec<usize>> = scan_hugepages().and_then(|x| x.into_iter().collect());
-	if let Ok(ref mut arr) = val.as_mut() {
-	    arr.sort_unstable();
+/// Format a size in kB human-readably, in the largest of `GB`, `MB`, or `kB` that it is a whole multiple of.
+fn fmt_kilobytes(kilobytes: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result
+{
+    const MB: usize = 1024;
+    const GB: usize = MB * 1024;
+    match kilobytes {
+	0 => f.write_str("0kB"),
+	kb if kb.is_multiple_of(GB) => write!(f, "{}GB", kb / GB),
+	kb if kb.is_multiple_of(MB) => write!(f, "{}MB", kb / MB),
+	kb => write!(f, "{kb}kB"),
+    }
+}
+
+/// Error for when parsing a `HugePage` from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseHugePageError(String);
+
+impl error::Error for ParseHugePageError{}
+impl fmt::Display for ParseHugePageError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	write!(f, "invalid huge-page size {:?}: expected a size such as \"2MB\", \"1GB\", or \"512kB\", or one of default, smallest, largest", self.0)
+    }
+}
+
+impl std::str::FromStr for HugePage
+{
+    type Err = ParseHugePageError;
+
+    /// Parse a huge-page specification.
+    ///
+    /// * `default` - `Static(MapHugeFlag::HUGE_DEFAULT)`
+    /// * `smallest`/`largest` - `Smallest`/`Largest`
+    /// * A size with a `kB`, `MB`, or `GB` suffix (case-insensitive, `B` optional), such as `2MB` - `Dynamic` with that size. A size without a suffix is in kB.
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+	let error = || ParseHugePageError(s.to_owned());
+	let spec = s.trim();
+	if spec.eq_ignore_ascii_case("default") {
+	    return Ok(Self::Static(MapHugeFlag::HUGE_DEFAULT));
+	} else if spec.eq_ignore_ascii_case("smallest") {
+	    return Ok(Self::Smallest);
+	} else if spec.eq_ignore_ascii_case("largest") {
+	    return Ok(Self::Largest);
+	}
+
+	let digits = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+	let (number, unit) = spec.split_at(digits);
+	let number: usize = number.parse().map_err(|_| error())?;
+	let unit = unit.trim_start();
+	let unit = unit.strip_suffix(['B', 'b']).unwrap_or(unit);
+	let shift = match unit {
+	    "" | "k" | "K" => 0,
+	    "m" | "M" => 10,
+	    "g" | "G" => 20,
+	    _ => return Err(error()),
 	};
-	val
+	match number.checked_shl(shift) {
+	    Some(kilobytes) if kilobytes >> shift == number && kilobytes != 0 => Ok(Self::Dynamic { kilobytes }),
+	    _ => Err(error()),
+	}
+    }
+}
+
+impl fmt::Display for HugePage
+{
+    /// Format this specification in a form accepted by `from_str()`, where possible.
+    ///
+    /// `Selected` specifications cannot be represented as strings, and are formatted as `<selector>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	match self {
+	    Self::Static(huge) => fmt::Display::fmt(huge, f),
+	    Self::Dynamic { kilobytes } => fmt_kilobytes(*kilobytes, f),
+	    Self::Smallest => f.write_str("smallest"),
+	    Self::Largest => f.write_str("largest"),
+	    Self::Selected(_) => f.write_str("<selector>"),
+	}
+    }
+}
+
+/// Scan the system for its sorted huge-page sizes (in kB).
+fn scan_sorted_hugepages() -> io::Result<Vec<usize>>
+{
+    let mut val: io::Result<Vec<usize>> = scan_hugepages().and_then(|x| x.into_iter().collect());
+    if let Ok(ref mut arr) = val.as_mut() {
+	arr.sort_unstable();
     };
+    val
+}
+
+/// Compute the flag for each of the huge-page sizes (in kB) `sizes`.
+fn hugepage_flags(sizes: &io::Result<Vec<usize>>) -> io::Result<Vec<MapHugeFlag>>
+{
+    sizes.as_ref()
+	.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("SYSTEM_HUGEPAGES failed with error {err}")))
+	.map(|vec| vec.iter().map(|&size| MapHugeFlag::calculate_or_default(size)).collect())
+}
+
+lazy_static! {
+    /// A persistent invocation of `scan_hugepages()`, sorted. Updated by `refresh_hugepage_cache()`.
+    pub(crate) static ref SYSTEM_HUGEPAGE_SIZES: RwLock<Arc<io::Result<Vec<usize>>>> = RwLock::new(Arc::new(scan_sorted_hugepages()));
 
     /// A list of all availble huge-page flags if enumeration of them is possible.
     ///
-    /// This is created from a persistent invocation of `scan_hugepages()`.
-    pub static ref SYSTEM_HUGEPAGES: io::Result<Vec<MapHugeFlag>> =
-	SYSTEM_HUGEPAGE_SIZES.as_ref()
-	.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("SYSTEM_HUGEPAGES failed with error {err}")))
-	.map(|vec| vec.iter().map(|&size| MapHugeFlag::calculate_or_default(size)).collect());
+    /// This is created from a persistent invocation of `scan_hugepages()`, and is updated by `refresh_hugepage_cache()`.
+    /// A refresh replaces the snapshot rather than modifying it, so a snapshot cloned out of the lock stays valid after a refresh (it just describes the system as it was.)
+    pub static ref SYSTEM_HUGEPAGES: RwLock<Arc<io::Result<Vec<MapHugeFlag>>>> = RwLock::new(Arc::new(hugepage_flags(&cached_hugepage_sizes())));
+}
+
+/// Get the current snapshot of a cached value, ignoring poisoning (the snapshots are immutable.)
+#[inline]
+fn read_cache<T: ?Sized>(cache: &RwLock<Arc<T>>) -> Arc<T>
+{
+    cache.read().unwrap_or_else(std::sync::PoisonError::into_inner).clone()
+}
+
+/// The cached, sorted huge-page sizes (in kB) of the system. See `SYSTEM_HUGEPAGE_SIZES`.
+#[inline]
+pub(crate) fn cached_hugepage_sizes() -> Arc<io::Result<Vec<usize>>>
+{
+    read_cache(&SYSTEM_HUGEPAGE_SIZES)
+}
+
+/// The huge-page sizes (in kB) available on the system, sorted ascending.
+///
+/// This is the cached result of `scan_hugepages()`, so it is only re-scanned by `refresh_hugepage_cache()`.
+/// An owned copy of the current snapshot is returned, which is not affected by later refreshes.
+///
+/// # Returns
+/// If the system could not be scanned, an error of the same kind as the scan's error is returned.
+pub fn system_hugepage_sizes() -> io::Result<Vec<usize>>
+{
+    match cached_hugepage_sizes().as_ref() {
+	Ok(sizes) => Ok(sizes.clone()),
+	Err(err) => Err(io::Error::new(err.kind(), format!("Failed to determine available huge-page sizes: {err}"))),
+    }
+}
+
+/// Re-scan the system for available huge-page sizes, and replace the cached `SYSTEM_HUGEPAGES` (and sizes) with the result.
+///
+/// Long-lived processes can call this after the system's huge-page pools have been changed (e.g. by an administrator) to pick up the new configuration.
+/// Both caches are replaced at once. Snapshots previously cloned out of `SYSTEM_HUGEPAGES` keep the old values, and are freed once they are dropped.
+///
+/// # Returns
+/// If the scan fails, the error is returned and the caches are left unchanged.
+pub fn refresh_hugepage_cache() -> io::Result<()>
+{
+    let sizes = Ok(scan_sorted_hugepages()?);
+    let flags = hugepage_flags(&sizes);
+
+    // Initialising `SYSTEM_HUGEPAGES` reads `SYSTEM_HUGEPAGE_SIZES`, so it must not happen while its write lock is held below.
+    lazy_static::initialize(&SYSTEM_HUGEPAGE_SIZES);
+    lazy_static::initialize(&SYSTEM_HUGEPAGES);
+
+    let mut sizes_lock = SYSTEM_HUGEPAGE_SIZES.write().unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut flags_lock = SYSTEM_HUGEPAGES.write().unwrap_or_else(std::sync::PoisonError::into_inner);
+    *sizes_lock = Arc::new(sizes);
+    *flags_lock = Arc::new(flags);
+    Ok(())
 }
 
 /// Scan the system for available huge-page sizes (in kB).
@@ -319,22 +646,7 @@ pub fn scan_hugepages() -> io::Result<impl IntoIterator<Item=io::Result<usize>>
 			Ok(next) => next.file_name(),
 			Err(err) => return Some(Err(err)),
 		    };
-		    let kbs = if let Some(dash) = memchr::memchr(b'-', path.as_bytes()) {
-			let name = &path.as_bytes()[(dash+1)..];
-			if let Some(k_loc) = memchr::memrchr(b'k', &name) {
-			    &name[..k_loc]
-			} else {
-			    continue
-			}
-		    } else {
-			continue
-		    };
-		    let kb = if let Ok(kbs) = std::str::from_utf8(kbs) {
-			kbs.parse::<usize>().ok()
-		    } else {
-			continue
-		    };
-		    match kb {
+		    match parse_hugepage_dir_name(path.as_bytes()) {
 			None => continue,
 			valid => valid.map(Ok)
 		    }
@@ -347,3 +659,243 @@ pub fn scan_hugepages() -> io::Result<impl IntoIterator<Item=io::Result<usize>>
     
     Ok(FilteredIterator(dir))
 }
+
+/// Parse the size (in kB) from a huge-page directory name of the form `hugepages-<size>kB`.
+fn parse_hugepage_dir_name(name: &[u8]) -> Option<usize>
+{
+    let kbs = {
+	let dash = memchr::memchr(b'-', name)?;
+	let name = &name[(dash+1)..];
+	let k_loc = memchr::memrchr(b'k', name)?;
+	&name[..k_loc]
+    };
+    std::str::from_utf8(kbs).ok()?.parse::<usize>().ok()
+}
+
+/// The huge-page pools available on a single NUMA node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeHugePages
+{
+    /// The id of the NUMA node (e.g. `0` for `node0`.)
+    pub node: usize,
+    /// Each huge-page size (in kB) available on this node, alongside the number of free pages of that size: `(size_kb, nr_free)`.
+    pub pages: Vec<(usize, usize)>,
+}
+
+/// Scan each NUMA node on the system for its available huge-page sizes (in kB) and the number of free pages of each size.
+///
+/// # Returns
+/// If reading the directory `NUMA_NODE_LOCATION`, or reading an entry within it, fails, then the error is returned.
+///
+/// Entries that are not NUMA nodes, nodes without a huge-page directory, and huge-page entries that are not parsed correctly are skipped.
+pub fn scan_hugepages_numa() -> io::Result<Vec<NodeHugePages>>
+{
+    #[inline]
+    fn parse_node_dir_name(name: &[u8]) -> Option<usize>
+    {
+	let id = name.strip_prefix(b"node")?;
+	std::str::from_utf8(id).ok()?.parse::<usize>().ok()
+    }
+
+    fn read_free(dir: &Path) -> io::Result<Option<usize>>
+    {
+	let free = fs::read_to_string(dir.join("free_hugepages"))?;
+	Ok(free.trim().parse::<usize>().ok())
+    }
+
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir(NUMA_NODE_LOCATION)? {
+	let entry = entry?;
+	let Some(node) = parse_node_dir_name(entry.file_name().as_bytes()) else { continue };
+
+	let dir = match fs::read_dir(entry.path().join("hugepages")) {
+	    Ok(dir) => dir,
+	    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+	    Err(err) => return Err(err),
+	};
+	let mut pages = Vec::new();
+	for entry in dir {
+	    let entry = entry?;
+	    let Some(size) = parse_hugepage_dir_name(entry.file_name().as_bytes()) else { continue };
+	    if let Some(free) = read_free(&entry.path())? {
+		pages.push((size, free));
+	    }
+	}
+	pages.sort_unstable();
+	nodes.push(NodeHugePages {
+	    node,
+	    pages,
+	});
+    }
+    nodes.sort_unstable_by_key(|n| n.node);
+    Ok(nodes)
+}
+
+/// Get the path of the `nr_hugepages` file for the huge-page pool of size `size_kb` (in kB).
+///
+/// # Returns
+/// If the system does not expose a huge-page pool of this size in `HUGEPAGE_LOCATION`, an error of kind `NotFound` is returned.
+fn nr_hugepages_path(size_kb: usize) -> io::Result<PathBuf>
+{
+    let dir = Path::new(HUGEPAGE_LOCATION).join(format!("hugepages-{size_kb}kB"));
+    if !dir.is_dir() {
+	return Err(io::Error::new(io::ErrorKind::NotFound, format!("No huge-page pool of size {size_kb}kB is present in {HUGEPAGE_LOCATION}")));
+    }
+    Ok(dir.join("nr_hugepages"))
+}
+
+/// Read the number of huge-pages of size `size_kb` (in kB) currently reserved in the system's pool.
+///
+/// # Returns
+/// If the system does not have a huge-page pool of this size, an error of kind `NotFound` is returned.
+/// If reading or parsing the pool's `nr_hugepages` fails, then that error is returned.
+pub fn nr_hugepages(size_kb: usize) -> io::Result<usize>
+{
+    let value = fs::read_to_string(nr_hugepages_path(size_kb)?)?;
+    value.trim().parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Set the number of huge-pages of size `size_kb` (in kB) reserved in the system's pool to `n`.
+///
+/// # Note
+/// This requires privileges (usually `CAP_SYS_ADMIN`.) The kernel may not be able to reserve all `n` pages; use `nr_hugepages()` to check how many actually were.
+///
+/// # Returns
+/// If the system does not have a huge-page pool of this size, an error of kind `NotFound` is returned.
+/// If writing the pool's `nr_hugepages` fails, then that error is returned.
+pub fn set_nr_hugepages(size_kb: usize, n: usize) -> io::Result<()>
+{
+    fs::write(nr_hugepages_path(size_kb)?, n.to_string())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn calculate_matches_libc()
+    {
+	assert_eq!(MapHugeFlag::calculate(NonZeroUsize::new(2048).unwrap()), MapHugeFlag::HUGE_2MB);
+	assert_eq!(MapHugeFlag::try_calculate(1024 * 1024), Some(MapHugeFlag::HUGE_1GB));
+    }
+
+    #[test]
+    fn calculate_encodes_log2_bytes()
+    {
+	// `MAP_HUGE_*` holds log2 of the size in bytes, not in kB: 64kB is `16 << MAP_HUGE_SHIFT`.
+	assert_eq!(MapHugeFlag::calculate(NonZeroUsize::new(64).unwrap()).get_mask(), 16 << MAP_HUGE_SHIFT);
+	assert_eq!(MapHugeFlag::try_calculate(2048).map(MapHugeFlag::get_mask), Some(21 << MAP_HUGE_SHIFT));
+	// Sizes that are not a power of two are rounded down to one.
+	assert_eq!(MapHugeFlag::try_calculate(3072), MapHugeFlag::try_calculate(2048));
+
+	for kilobytes in [4, 512, 2048, 32 * 1024, 1024 * 1024] {
+	    let shift = MapHugeFlag::try_calculate(kilobytes).unwrap().get_mask() >> MAP_HUGE_SHIFT;
+	    assert_eq!(1usize << shift, kilobytes * 1024);
+	}
+	assert_eq!(MapHugeFlag::try_calculate(0), None);
+	assert_eq!(MapHugeFlag::calculate_or_default(0), MapHugeFlag::HUGE_DEFAULT);
+    }
+
+    #[test]
+    fn hugepage_rounding()
+    {
+	const MB2: usize = 2 * 1024 * 1024;
+	assert_eq!(round_up_to_hugepage(0, MapHugeFlag::HUGE_2MB), Some(0));
+	assert_eq!(round_up_to_hugepage(1, MapHugeFlag::HUGE_2MB), Some(MB2));
+	assert_eq!(round_up_to_hugepage(MB2, MapHugeFlag::HUGE_2MB), Some(MB2));
+	assert_eq!(round_up_to_hugepage(MB2 + 1, MapHugeFlag::HUGE_2MB), Some(MB2 * 2));
+	assert_eq!(round_up_to_hugepage(usize::MAX, MapHugeFlag::HUGE_2MB), None);
+	assert_eq!(round_down_to_hugepage(MB2 - 1, MapHugeFlag::HUGE_2MB), Some(0));
+	assert_eq!(round_down_to_hugepage(MB2 * 2 + 1, MapHugeFlag::HUGE_2MB), Some(MB2 * 2));
+    }
+
+    #[test]
+    fn hugepage_parse_display()
+    {
+	assert_eq!("2MB".parse(), Ok(HugePage::Dynamic { kilobytes: 2048 }));
+	assert_eq!("1gb".parse(), Ok(HugePage::Dynamic { kilobytes: 1024 * 1024 }));
+	assert_eq!("512kB".parse(), Ok(HugePage::Dynamic { kilobytes: 512 }));
+	assert_eq!("64".parse(), Ok(HugePage::Dynamic { kilobytes: 64 }));
+	assert_eq!("Largest".parse(), Ok(HugePage::Largest));
+	for bad in ["", "0MB", "2TB", "MB", "-2MB", "99999999999999999999GB"] {
+	    assert!(bad.parse::<HugePage>().is_err(), "{bad:?} parsed");
+	}
+
+	for spec in ["2MB", "1GB", "512kB", "1536kB", "default", "smallest", "largest"] {
+	    assert_eq!(spec.parse::<HugePage>().unwrap().to_string(), spec);
+	}
+	assert_eq!(HugePage::Static(MapHugeFlag::HUGE_1GB).to_string(), "1GB");
+    }
+
+    #[test]
+    fn map_huge_flag_size()
+    {
+	assert_eq!(MapHugeFlag::HUGE_2MB.size_bytes(), Some(2 * 1024 * 1024));
+	assert_eq!(MapHugeFlag::HUGE_DEFAULT.size_bytes(), None);
+	assert_eq!(MapHugeFlag::HUGE_2MB.to_string(), "2MB");
+	assert_eq!(MapHugeFlag::HUGE_1GB.to_string(), "1GB");
+	assert_eq!(MapHugeFlag::calculate(NonZeroUsize::new(64).unwrap()).to_string(), "64kB");
+	assert_eq!(MapHugeFlag::HUGE_DEFAULT.to_string(), "default");
+    }
+
+    #[test]
+    fn system_sizes_sorted()
+    {
+	if let Ok(sizes) = system_hugepage_sizes() {
+	    assert!(sizes.windows(2).all(|w| w[0] < w[1]));
+	    let flags = SYSTEM_HUGEPAGES.read().unwrap().clone();
+	    assert_eq!(flags.as_ref().as_ref().map(Vec::len).ok(), Some(sizes.len()));
+	}
+    }
+
+    #[test]
+    fn refresh_keeps_old_snapshot()
+    {
+	let before = SYSTEM_HUGEPAGES.read().unwrap().clone();
+	match refresh_hugepage_cache() {
+	    Ok(()) => {
+		let after = SYSTEM_HUGEPAGES.read().unwrap().clone();
+		assert!(!Arc::ptr_eq(&before, &after));
+		// The old snapshot is still readable.
+		assert_eq!(before.as_ref().as_ref().ok(), after.as_ref().as_ref().ok());
+	    },
+	    // No huge-page support on this system: the cache is unchanged.
+	    Err(_) => assert!(Arc::ptr_eq(&before, &SYSTEM_HUGEPAGES.read().unwrap())),
+	}
+    }
+
+    #[test]
+    fn refresh_first_in_fresh_process()
+    {
+	use std::process::{Command, Stdio};
+	use std::time::{Duration, Instant};
+	const CHILD: &str = "MAPPED_FILE_TEST_REFRESH_FIRST";
+	// In the child, refreshing must be the first use of the caches.
+	if std::env::var_os(CHILD).is_some() {
+	    let _ = refresh_hugepage_cache();
+	    return;
+	}
+
+	let mut child = Command::new(std::env::current_exe().unwrap())
+	    .args(["--exact", "hugetlb::tests::refresh_first_in_fresh_process", "--test-threads=1"])
+	    .env(CHILD, "1")
+	    .stdout(Stdio::null())
+	    .stderr(Stdio::null())
+	    .spawn()
+	    .unwrap();
+	let deadline = Instant::now() + Duration::from_secs(10);
+	let status = loop {
+	    if let Some(status) = child.try_wait().unwrap() {
+		break status;
+	    }
+	    if Instant::now() > deadline {
+		child.kill().unwrap();
+		child.wait().unwrap();
+		panic!("refresh_hugepage_cache() deadlocked when called first");
+	    }
+	    std::thread::sleep(Duration::from_millis(10));
+	};
+	assert!(status.success());
+    }
+}
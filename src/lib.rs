@@ -23,7 +23,11 @@ use std::{
 };
 
 mod ffi;
-use ffi::c_try;
+use ffi::{
+    c_try,
+    c_retry,
+    c_flags,
+};
 
 
 pub mod hugetlb;
@@ -40,39 +44,71 @@ use hugetlb::{
     MapHugeFlag,
 };
 
-mod uniq;
+pub mod uniq;
 use uniq::UniqueSlice;
 
 mod flags;
 pub use flags::*;
 
 pub mod err;
-use err::{
-    os_error,
-    opaque,
-};
+use err::opaque;
+
+/// The result of a fallible operation that does not need to give back the value it was passed, with `io::Error` as the default error type.
+///
+/// Operations that do give the value back on failure (e.g. `MappedFile::try_new()`) return a `TryNewError` instead, which can be converted into an `io::Error` with `?`. Most of them have a counterpart (e.g. `MappedFile::new()`) that returns this instead.
+pub type Result<T, E = io::Error> = std::result::Result<T, E>;
+
+mod secure;
+pub use secure::SecureMappedFile;
+
+mod frozen;
+pub use frozen::ReadOnly;
+
+#[cfg(feature="tokio")]
+mod async_rt;
+
+#[cfg(feature="memmap2-compat")]
+pub mod compat;
 
 
+/// The mapped memory, and the size of the `PROT_NONE` guard region reserved on either side of it (see `MappedFile::try_new_guarded()`), which is unmapped along with it.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-#[repr(transparent)]
-struct MappedSlice(UniqueSlice<u8>);
+struct MappedSlice(UniqueSlice<u8>, usize);
 
 unsafe impl Send for MappedSlice{}
 unsafe impl Sync for MappedSlice{}
 
+impl MappedSlice
+{
+    /// A mapping of length `0`, which does not refer to any mapped memory and is never unmapped.
+    #[inline]
+    fn empty() -> Self
+    {
+	let dangling = NonNull::dangling();
+	Self(UniqueSlice {
+	    mem: dangling,
+	    end: dangling,
+	}, 0)
+    }
+}
+
 impl ops::Drop for MappedSlice
 {
     #[inline]
     fn drop(&mut self) 
     {
+	if self.0.is_empty() {
+	    return;
+	}
+	let guard = self.1;
 	unsafe {
-            libc::munmap(self.0.as_mut_ptr() as *mut _, self.0.len());
+            libc::munmap(self.0.as_mut_ptr().sub(guard) as *mut _, self.0.len() + guard * 2);
 	}
     }
 }
 
 /// A memory mapping over file `T`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(PartialEq, Eq, Hash)]
 pub struct MappedFile<T>
 {
     file: T,
@@ -85,6 +121,25 @@ fn _panic_invalid_address() -> !
     panic!("Invalid/unsupported address returned from mmap()")
 }
 
+/// Reject `perm` if it is both writable and executable, when the `strict-wxorx` feature is enabled.
+///
+/// # Returns
+/// An error of kind `PermissionDenied` if the check is enabled and `perm` is writable and executable, otherwise `Ok(())`.
+#[inline]
+fn check_wxorx(perm: Perm) -> io::Result<()>
+{
+    #[cfg(feature="strict-wxorx")]
+    {
+	let prot = perm.get_prot();
+	if prot & libc::PROT_WRITE != 0 && prot & libc::PROT_EXEC != 0 {
+	    return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("Refusing to create a writable and executable mapping ({perm:?})")));
+	}
+    }
+    #[cfg(not(feature="strict-wxorx"))]
+    let _ = perm;
+    Ok(())
+}
+
 /// Get the current system page size
 pub fn get_page_size() -> usize
 {
@@ -101,6 +156,23 @@ pub fn get_page_size() -> usize
     v as usize
 }
 
+/// Round `n` up to the next multiple of the system page size. (see `get_page_size()`.)
+///
+/// # Panics
+/// If the rounded value overflows.
+#[inline]
+pub fn round_up_to_page(n: usize) -> usize
+{
+    n.checked_next_multiple_of(get_page_size()).expect("Rounding up to page size overflowed")
+}
+
+/// Round `n` down to the previous multiple of the system page size. (see `get_page_size()`.)
+#[inline]
+pub fn round_down_to_page(n: usize) -> usize
+{
+    n - (n % get_page_size())
+}
+
 impl<T> MappedFile<T> {
     /// A reference to the mapped backing file
     #[inline]
@@ -129,6 +201,78 @@ impl<T> MappedFile<T> {
         drop(self.map);
         self.file
     }
+
+    /// Unmap the memory, and return the file descriptor of `T` as a `std::fs::File`.
+    ///
+    /// This is `into_inner()` followed by converting the raw file descriptor into a `File`, which then owns it.
+    ///
+    /// # Warning
+    /// The mapping is unmapped first. As with `into_inner()`, you should call `flush()` first if the map is shared or refers to a persistent file on disk.
+    #[inline]
+    pub fn into_file(self) -> std::fs::File
+    where T: IntoRawFd
+    {
+	unsafe {
+	    std::fs::File::from_raw_fd(self.into_inner().into_raw_fd())
+	}
+    }
+
+    /// Decompose into the address and length of the mapped memory, and the file, without unmapping it.
+    ///
+    /// The memory stays mapped until it is either reconstructed into a `MappedFile` with `from_raw_parts()` and dropped, or unmapped manually with `munmap()`. If neither happens, it is leaked.
+    ///
+    /// # Note
+    /// Any guard pages around the mapping (see `try_new_guarded()`) are unmapped here, since they cannot be represented by the returned parts.
+    pub fn into_raw_parts(self) -> (*mut u8, usize, T)
+    {
+	let (ptr, len) = self.raw_parts();
+	let guard = self.map.1;
+	if guard != 0 {
+	    unsafe {
+		libc::munmap(ptr.sub(guard) as *mut _, guard);
+		libc::munmap(ptr.add(round_up_to_page(len)) as *mut _, guard);
+	    }
+	}
+	mem::forget(self.map);
+	(ptr, len, self.file)
+    }
+
+    /// Reconstruct a mapping from the parts returned by `into_raw_parts()`.
+    ///
+    /// The returned `MappedFile` owns the memory again, and unmaps it on drop.
+    ///
+    /// # Safety
+    /// * `ptr` and `len` **must** describe a single live mapping, e.g. as returned by a prior call to `into_raw_parts()`, or by a successful `mmap(NULL, len, ...)` call, so that `munmap(ptr, len)` on drop is valid.
+    /// * Nothing else may unmap, or hold references into, that memory for the lifetime of the returned value.
+    /// * `file` should be the file that is mapped, as methods like `flush()` and `remap_fixed()` assume so.
+    /// * If `len` is `0`, `ptr` is ignored and nothing is unmapped on drop.
+    ///
+    /// # Panics
+    /// If `ptr` is null, or `ptr + len` overflows.
+    pub unsafe fn from_raw_parts(ptr: *mut u8, len: usize, file: T) -> Self
+    {
+	if len == 0 {
+	    return Self {
+		file,
+		map: MappedSlice::empty(),
+	    };
+	}
+	let mem = match NonNull::new(ptr) {
+	    Some(n) => n,
+	    None => panic!("from_raw_parts(): null mapping address"),
+	};
+	let end = match (ptr as usize).checked_add(len) {
+	    Some(_) => NonNull::new_unchecked(ptr.add(len)),
+	    None => panic!("from_raw_parts(): mapping of {len} bytes at {ptr:p} overflows the address space"),
+	};
+	Self {
+	    file,
+	    map: MappedSlice(UniqueSlice {
+		mem,
+		end,
+	    }, 0),
+	}
+    }
 }
 
 impl<T: AsRawFd> MappedFile<T> {
@@ -140,12 +284,18 @@ impl<T: AsRawFd> MappedFile<T> {
     /// If `mmap()` fails, then the current `errno` is returned alongside the `file` that was passed in, otherwise, a new mapping is
     /// constructed over `file`, and that is returned.
     ///
+    /// # W^X
+    /// When the `strict-wxorx` feature is enabled, a `perm` that is both writable and executable (e.g. `Perm::WRX`) is rejected with an error of kind `PermissionDenied` before `mmap()` is called.
+    ///
     /// # Panics
     /// If `mmap()` succeeds, but returns an invalid address (e.g. 0)
     pub fn try_new(file: T, len: usize, perm: Perm, flags: impl flags::MapFlags) -> Result<Self, TryNewError<T>>
     {
 	
 	const NULL: *mut libc::c_void = ptr::null_mut();
+	if let Err(err) = check_wxorx(perm) {
+	    return Err(TryNewError::wrap((err, file)));
+	}
         let fd = file.as_raw_fd();
         let slice = match unsafe {
             mmap(ptr::null_mut(), len, perm.get_prot(), flags.get_mmap_flags(), fd, 0)
@@ -164,11 +314,118 @@ impl<T: AsRawFd> MappedFile<T> {
         };
         Ok(Self {
             file,
-            map: MappedSlice(slice)
+            map: MappedSlice(slice, 0)
         })
     }
 
-    
+    /// Map the file `file` to `len` bytes between two inaccessible guard pages.
+    ///
+    /// A region of `len` (rounded up to the page size) plus two pages is first reserved as `PROT_NONE`, and the file is then mapped over the middle of it with `MAP_FIXED`.
+    /// Any access to the page directly before or after the mapping faults (`SIGSEGV`) instead of silently touching whatever else happens to be mapped there.
+    ///
+    /// `len()` reports only the usable middle region. The whole reserved span, guard pages included, is unmapped in one call on drop.
+    ///
+    /// # Note
+    /// The guard pages only start at page boundaries, so accesses between `len` and the end of its last page do not fault.
+    ///
+    /// # Returns
+    /// If `len` is `0`, an error of kind `InvalidInput` is returned.
+    /// If either `mmap()` call fails, then the error is returned along with the file, and nothing is left mapped.
+    ///
+    /// # Panics
+    /// If `mmap()` succeeds, but returns an invalid address (e.g. 0)
+    pub fn try_new_guarded(file: T, len: usize, perm: Perm, flags: impl flags::MapFlags) -> Result<Self, TryNewError<T>>
+    {
+	const NULL: *mut libc::c_void = ptr::null_mut();
+	if let Err(err) = check_wxorx(perm) {
+	    return Err(TryNewError::wrap((err, file)));
+	}
+	if len == 0 {
+	    return Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, "Cannot create a guarded mapping of length 0"), file)));
+	}
+	let guard = get_page_size();
+	let total = round_up_to_page(len) + guard * 2;
+
+	let root = match unsafe {
+	    mmap(NULL, total, libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+	} {
+	    MAP_FAILED => return Err(TryNewError::wrap_last_error(file)),
+	    NULL => _panic_invalid_address(),
+	    ptr => ptr as *mut u8,
+	};
+	let mem = unsafe { root.add(guard) };
+	match unsafe {
+	    mmap(mem as *mut _, len, perm.get_prot(), flags.get_mmap_flags() | libc::MAP_FIXED, file.as_raw_fd(), 0)
+	} {
+	    MAP_FAILED => {
+		let err = io::Error::last_os_error();
+		unsafe {
+		    libc::munmap(root as *mut _, total);
+		}
+		Err(TryNewError::wrap((err, file)))
+	    },
+	    _ => Ok(Self {
+		file,
+		map: MappedSlice(unsafe {
+		    UniqueSlice {
+			mem: NonNull::new_unchecked(mem),
+			end: NonNull::new_unchecked(mem.add(len)),
+		    }
+		}, guard),
+	    }),
+	}
+    }
+
+
+    /// Map the file `file` to `len` bytes, rounded up to the next multiple of the page size (see `get_page_size()`.)
+    ///
+    /// `mmap()` always maps whole pages, so the bytes between `len` and the end of its last page are mapped regardless.
+    /// Unlike `try_new()`, where `len()` reports the requested `len` and the slack bytes are inaccessible, the mapping returned here reports the padded length, so the full mapped region is usable.
+    /// This is useful for e.g. anonymous scratch buffers, where the extra bytes are free.
+    ///
+    /// # Note
+    /// Only the regular page size is used for rounding. For huge-page mappings, `len` should already be a multiple of the huge-page size.
+    ///
+    /// # Returns
+    /// See `try_new()`. If rounding `len` up would overflow, an error of kind `InvalidInput` is returned alongside `file`.
+    #[inline]
+    pub fn try_new_padded(file: T, len: usize, perm: Perm, flags: impl flags::MapFlags) -> Result<Self, TryNewError<T>>
+    {
+	match len.checked_next_multiple_of(get_page_size()) {
+	    Some(len) => Self::try_new(file, len, perm, flags),
+	    None => Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, format!("Mapping length {len} cannot be rounded up to the page size")), file))),
+	}
+    }
+
+    /// Map the file `file` to `len` bytes with huge-pages of the size specified by `huge`, after checking that size is available on the system.
+    ///
+    /// `mmap()` fails with a generic `EINVAL` when asked for a huge-page size the kernel does not support. This checks the size against `hugetlb::SYSTEM_HUGEPAGES` first, so the error says which sizes are available instead.
+    /// The check reads the cached huge-page sizes, so it is cheap, but it is kept out of `try_new()` for callers that have already validated their configuration.
+    ///
+    /// # Returns
+    /// If `huge` cannot be computed, an error of kind `InvalidInput` (wrapping `hugetlb::HugePageCalcErr`) is returned alongside `file`.
+    /// If the size is not available, an error of kind `Unsupported` (wrapping `hugetlb::HugePageUnavailable`, which lists the available sizes) is returned alongside `file`.
+    /// If the available sizes cannot be determined, or `mmap()` fails, then that error is returned alongside `file`.
+    pub fn try_new_hugetlb_checked(file: T, len: usize, perm: Perm, flags: Flags, huge: HugePage) -> Result<Self, TryNewError<T>>
+    {
+	let huge = match MapHugeFlag::try_from(huge) {
+	    Ok(huge) => huge,
+	    Err(error) => return Err(TryNewError::wrap((error, file))),
+	};
+	if let Err(error) = huge.check_available() {
+	    return Err(TryNewError::wrap((error, file)));
+	}
+
+	struct HugeFlags(Flags, MapHugeFlag);
+	unsafe impl MapFlags for HugeFlags
+	{
+	    #[inline(always)]
+	    fn get_mmap_flags(&self) -> libc::c_int {
+		self.0.get_flags() | self.1.mmap_flags()
+	    }
+	}
+	Self::try_new(file, len, perm, HugeFlags(flags, huge))
+    }
 
     /// Returns a dual mapping `(tx, rx)`, into the same file.
     ///
@@ -211,18 +468,46 @@ impl<T: AsRawFd> MappedFile<T> {
     /// * `buffer::Shared` - A `Send`able mapping, use this for concurrent processing.
     /// * `buffer::Private` - A `!Send` mapping, use this for when both returned maps are only used on the same thread that this function was called from.
     ///
+    /// # Anonymous buffers
+    /// If `file` has no file descriptor (e.g. `Anonymous`), then an in-memory file of `len` bytes is created to back both mappings. (see `try_new_buffer()`.)
+    ///
     /// # Note
     /// `len` **must** be a multiple of the used page size (see `get_page_size()`) (or hugepage size, if `flags` is set to use one) for this to work.
     pub fn try_shared<B: buffer::TwoBufferProvider<T>>(file: T, len: usize, flags: impl flags::MapFlags) -> Result<(MappedFile<B>, MappedFile<B>), TryNewError<T>>
+    {
+	Self::try_new_buffer(file, len, flags)
+    }
+
+    /// Returns a dual mapping `(tx, rx)`, into the same file.
+    ///
+    /// The sender `tx` is *write only*, and the receiver `rx` is *read only*; bytes written through `tx` are read back through `rx`. (see `try_shared()`.)
+    ///
+    /// # Anonymous buffers
+    /// If `file` has no file descriptor (e.g. `Anonymous`), then an in-memory file (`file::memory::MemoryFile`) of `len` bytes is created to back both mappings, and closed once they are mapped.
+    /// This means `MappedFile::try_new_buffer::<buffer::Shared<_>>(Anonymous, len, ())` creates an in-memory single-producer, single-consumer channel without the caller supplying a file.
+    ///
+    /// # Note
+    /// `len` **must** be a multiple of the used page size (see `get_page_size()`) (or hugepage size, if `flags` is set to use one), otherwise an error of kind `InvalidInput` is returned.
+    /// `flags` **must** be shared (e.g. `()` or `Flags::Shared`) for the two mappings to alias the same data.
+    #[inline]
+    pub fn try_new_buffer<B: buffer::TwoBufferProvider<T>>(file: T, len: usize, flags: impl flags::MapFlags) -> Result<(MappedFile<B>, MappedFile<B>), TryNewError<T>>
     {
 	Self::try_new_buffer_raw::<B>(file, len, None, false, flags)
     }
-    //TODO: XXX: Test this when we have implemented memfd.
+
     #[inline] 
     pub(crate) fn try_new_buffer_raw<B: buffer::TwoBufferProvider<T>>(file: T, len: usize, rings: impl Into<Option<std::num::NonZeroUsize>>, allow_unsafe_writes: bool, flags: impl flags::MapFlags) -> Result<(MappedFile<B>, MappedFile<B>), TryNewError<T>>
     {
 	const NULL: *mut libc::c_void = ptr::null_mut();
 
+	// The second mapping is placed directly after the first, which only works on a page boundary.
+	let page = MapHugeFlag::from_mmap_flags(flags.get_mmap_flags())
+	    .and_then(MapHugeFlag::page_size)
+	    .unwrap_or_else(get_page_size);
+	if !len.is_multiple_of(page) {
+	    return Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, format!("Buffer length {len} is not a multiple of the page size ({page} bytes)")), file)));
+	}
+
 	macro_rules! try_map_or {
 	    ($($tt:tt)*) => {
 		match unsafe {
@@ -240,7 +525,7 @@ impl<T: AsRawFd> MappedFile<T> {
 			    }
 			}
 		    })
-		}.map(MappedSlice)
+		}.map(|slice| MappedSlice(slice, 0))
 	    };
 	}
 	macro_rules! try_map {
@@ -260,7 +545,7 @@ impl<T: AsRawFd> MappedFile<T> {
 			    }
 			}
 		    }
-		})
+		}, 0)
 	    };
 	}
 
@@ -279,6 +564,14 @@ impl<T: AsRawFd> MappedFile<T> {
 	} else {
 	    (Perm::Writeonly.get_prot(), Perm::Readonly.get_prot())
 	};
+	// Anonymous buffers have no file to alias, so back them with an in-memory file. The mappings keep its memory alive after it is closed.
+	let backing = if file.as_raw_fd() < 0 {
+	    Some(unwrap!(file::memory::MemoryFile::with_size(len)))
+	} else {
+	    None
+	};
+	let rawfd = backing.as_ref().map_or_else(|| file.as_raw_fd(), AsRawFd::as_raw_fd);
+
 	// Move into dual buffer
 
 	let (tx, rx) = match rings.into() {
@@ -286,10 +579,11 @@ impl<T: AsRawFd> MappedFile<T> {
 		// No rings, just create two mappings at same addr.
 		let flags = flags.get_mmap_flags();
 		let mut root = try_map!(NULL, len * 2, libc::PROT_NONE, (flags & !libc::MAP_SHARED) | libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0);
-		let rawfd = file.as_raw_fd();
 		
 		let rm = try_map!(root.0.as_mut_ptr().add(len) as *mut _, len, prot_r, flags | libc::MAP_FIXED, rawfd, 0); // Map reader at offset `len` from `root`.
 		let tm = try_map!(root.0.as_mut_ptr() as *mut _, len, prot_w, flags | libc::MAP_FIXED, rawfd, 0);  // Map writer at `root`, unmapping the anonymous map used to reserve the pages.
+		// `root` is now entirely covered by `tm` and `rm`, which each unmap their own half. Dropping it would unmap both.
+		mem::forget(root);
 
 		let tf = B::from_value(file);
 		let rf = B::from_wrapper(tf.as_wrapper());
@@ -310,7 +604,6 @@ impl<T: AsRawFd> MappedFile<T> {
 		let flags = flags.get_mmap_flags();
 		let mut root = try_map!(NULL, full_len, libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0);
 		let pivots = {
-		    let rawfd = file.as_raw_fd();
 		    let pivots: io::Result<Vec<_>> = std::iter::successors(unsafe { Some(root.0.as_mut_ptr().add(full_len - (len * 2))) }, |&x| unsafe { Some(x.sub(len * 2)) }) // Map in reverse, from end of `root`, and overwrite the `root` mapping last.
 			.take(pages.get())
 			.map(|base| {
@@ -345,21 +638,137 @@ impl<T: AsRawFd> MappedFile<T> {
 	Self::try_new(file, len, perm, flags).map_err(Into::into)
     }
 
+    /// Map the file `file` to `len` bytes between two inaccessible guard pages. See `try_new_guarded()`.
+    ///
+    /// # Returns
+    /// If mapping fails, `file` is dropped and the error is returned. To retain `file`, use `try_new_guarded()`.
+    #[inline]
+    pub fn new_guarded(file: T, len: usize, perm: Perm, flags: impl MapFlags) -> Result<Self>
+    {
+	Self::try_new_guarded(file, len, perm, flags).map_err(Into::into)
+    }
+
+    /// Map the file `file` to `len` bytes, rounded up to the next multiple of the page size. See `try_new_padded()`.
+    ///
+    /// # Returns
+    /// If mapping fails, `file` is dropped and the error is returned. To retain `file`, use `try_new_padded()`.
+    #[inline]
+    pub fn new_padded(file: T, len: usize, perm: Perm, flags: impl MapFlags) -> Result<Self>
+    {
+	Self::try_new_padded(file, len, perm, flags).map_err(Into::into)
+    }
+
+    /// Map the file `file` to `len` bytes with huge-pages of the size specified by `huge`, after checking that size is available. See `try_new_hugetlb_checked()`.
+    ///
+    /// # Returns
+    /// If the check or mapping fails, `file` is dropped and the error is returned. To retain `file`, use `try_new_hugetlb_checked()`.
+    #[inline]
+    pub fn new_hugetlb_checked(file: T, len: usize, perm: Perm, flags: Flags, huge: HugePage) -> Result<Self>
+    {
+	Self::try_new_hugetlb_checked(file, len, perm, flags, huge).map_err(Into::into)
+    }
+
+    /// Returns a dual mapping `(tx, rx)`, into the same file. See `try_new_buffer()`.
+    ///
+    /// # Returns
+    /// If mapping fails, `file` is dropped and the error is returned. To retain `file`, use `try_new_buffer()`.
+    #[inline]
+    pub fn new_buffer<B: buffer::TwoBufferProvider<T>>(file: T, len: usize, flags: impl MapFlags) -> Result<(MappedFile<B>, MappedFile<B>)>
+    {
+	Self::try_new_buffer(file, len, flags).map_err(Into::into)
+    }
+
+    /// `msync()` the region `addr..addr+len`, re-issuing the call if it is interrupted by a signal.
+    #[inline]
+    fn msync_raw(addr: *mut u8, len: usize, flush: Flush) -> io::Result<()>
+    {
+        use libc::msync;
+	// An empty mapping's address is dangling, which `msync()` would reject.
+	if len == 0 {
+	    return Ok(());
+	}
+	match c_retry!(msync(addr as *mut _, len, flush.get_ms()) => -1) {
+	    0 => Ok(()),
+	    _ => Err(io::Error::last_os_error()),
+	}
+    }
+
     /// Sync the mapped memory to the backing file store via `msync()`.
     ///
     /// If this is a private mapping, or is mapped over a private file descriptor that does not refer to on-disk persistent storage, syncing the data is usually pointless.
     ///
+    /// If `msync()` is interrupted by a signal (`EINTR`), it is re-issued, so this is robust in signal-heavy programs.
+    ///
     /// # Returns
     /// If `msync()` fails.
     pub fn flush(&mut self, flush: Flush) -> io::Result<()>
     {
-        use libc::msync;
-        match unsafe {
-	    msync(self.map.0.as_mut_ptr() as *mut _, self.map.0.len(), flush.get_ms())
-	} {
-	    0 => Ok(()),
-	    _ => Err(io::Error::last_os_error())
-        }
+	Self::msync_raw(self.map.0.as_mut_ptr(), self.map.0.len(), flush)
+    }
+
+    /// Sync a sub-range of the mapped memory to the backing file store via `msync()`.
+    ///
+    /// The start of `range` is rounded down to the page it lies in, as `msync()` requires a page-aligned address.
+    /// As with `flush()`, an interrupted `msync()` is re-issued.
+    ///
+    /// # Returns
+    /// If `msync()` fails.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    pub fn flush_range(&mut self, range: impl ops::RangeBounds<usize>, flush: Flush) -> io::Result<()>
+    {
+	let (addr, len) = self.raw_page_range(range);
+	Self::msync_raw(addr, len, flush)
+    }
+
+    /// Sync a sub-range of the mapped memory, used as a builder-pattern alternative for `flush_range()`.
+    ///
+    /// # Returns
+    /// If `msync()` fails, then the `io::Error` along with the previous instance is returned.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    #[inline]
+    pub fn try_flushed_range(mut self, range: impl ops::RangeBounds<usize>, flush: Flush) -> Result<Self, TryNewError<Self>>
+    {
+	match self.flush_range(range, flush) {
+	    Ok(_) => Ok(self),
+	    Err(error) => Err(TryNewError {
+		error: Box::new(error),
+		value: self,
+	    })
+	}
+    }
+
+    /// Sync a sub-range of the mapped memory, used as a builder-pattern alternative for `flush_range()`.
+    ///
+    /// # Returns
+    /// If `msync()` fails, then the mapping is dropped and the error is returned. To keep the previous instance if the call fails, use `try_flushed_range()`.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    #[inline]
+    pub fn flushed_range(self, range: impl ops::RangeBounds<usize>, flush: Flush) -> io::Result<Self>
+    {
+	self.try_flushed_range(range, flush).map_err(Into::into)
+    }
+
+    /// Sync a sub-range of the mapped memory with `flush_range()`, then drop its pages with `evict()`.
+    ///
+    /// This is the order a streaming writer needs after finishing with a region of a shared mapping: the changes are written back to the file before the pages holding them are released, so nothing is lost.
+    /// For the write-back to be complete before the pages are dropped, `flush` should be `Flush::Wait`.
+    ///
+    /// # Returns
+    /// If `msync()` fails, then the error is returned and nothing is evicted.
+    /// If `madvise()` fails, then the error is returned.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    pub fn flush_and_evict(&mut self, range: impl ops::RangeBounds<usize> + Clone, flush: Flush) -> io::Result<()>
+    {
+	self.flush_range(range.clone(), flush)?;
+	self.evict(range)
     }
 
     /// Replace the mapped file object with another that aliases the same file descriptor.
@@ -394,6 +803,44 @@ impl<T: AsRawFd> MappedFile<T> {
     }
 }
 
+impl MappedFile<std::fs::File> {
+    /// Map the whole of an already opened `file` with memory protection as provided by `perm`, and mapping flags provided by `flags`.
+    ///
+    /// The length of the mapping is the size of the file, as reported by `fstat()`.
+    ///
+    /// # Zero-length files
+    /// If `file` is empty, then an empty mapping (see `is_empty()`) is returned without calling `mmap()`, which would otherwise fail.
+    ///
+    /// # Returns
+    /// If `fstat()` or `mmap()` fails, then the error is returned and `file` is dropped.
+    pub fn from_file(file: std::fs::File, perm: Perm, flags: impl MapFlags) -> io::Result<Self>
+    {
+	let len = usize::try_from(file.metadata()?.len()).map_err(|_| io::Error::new(io::ErrorKind::Unsupported, "File size exceeds pointer word width"))?;
+	if len == 0 {
+	    return Ok(Self {
+		file,
+		map: MappedSlice::empty(),
+	    });
+	}
+	Self::new(file, len, perm, flags)
+    }
+}
+
+impl TryFrom<std::fs::File> for MappedFile<std::fs::File>
+{
+    type Error = io::Error;
+
+    /// Map the whole of `file` read-write and shared. See `from_file()` for mapping it with other permissions or flags.
+    ///
+    /// # Returns
+    /// If `fstat()` or `mmap()` fails, then the error is returned and `file` is dropped. A file opened read-only fails with `EACCES`.
+    #[inline]
+    fn try_from(file: std::fs::File) -> Result<Self, Self::Error>
+    {
+	Self::from_file(file, Perm::ReadWrite, Flags::Shared)
+    }
+}
+
 impl<T> MappedFile<T> {
     #[inline(always)]
     fn raw_parts(&self) -> (*mut u8, usize)
@@ -401,49 +848,356 @@ impl<T> MappedFile<T> {
         (self.map.0.mem.as_ptr(), self.map.0.len())
     } 
 
+    /// Resolve `range` into the raw region of the mapping it covers, with the start rounded down to its page.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    fn raw_page_range(&self, range: impl ops::RangeBounds<usize>) -> (*mut u8, usize)
+    {
+	use ops::Bound;
+	let len = self.len();
+	let start = match range.start_bound() {
+	    Bound::Included(&n) => n,
+	    Bound::Excluded(&n) => n.checked_add(1).expect("Range start overflow"),
+	    Bound::Unbounded => 0,
+	};
+	let end = match range.end_bound() {
+	    Bound::Included(&n) => n.checked_add(1).expect("Range end overflow"),
+	    Bound::Excluded(&n) => n,
+	    Bound::Unbounded => len,
+	};
+	assert!(start <= end && end <= len, "Range {start}..{end} out of bounds for mapping of length {len}");
+	let start = round_down_to_page(start);
+	let (addr, _) = self.raw_parts();
+	(unsafe { addr.add(start) }, end - start)
+    }
+
     /// Set advise according to `adv`, and optionally advise the kernel on if the memory will be needed or not.
-    pub fn advise(&mut self, adv: Advice, needed: Option<bool>) -> io::Result<()>
+    ///
+    /// `adv` can be a single `Advice`, or an `AdviceSet` of several (built with `|`), which are each applied in turn.
+    ///
+    /// # Returns
+    /// If `adv` contains mutually exclusive advices (see `AdviceSet`), an error of kind `InvalidInput` is returned and nothing is applied.
+    /// If `madvise()` fails, then the error is returned. Advices applied before the failing one remain in effect.
+    pub fn advise(&mut self, adv: impl Into<AdviceSet>, needed: Option<bool>) -> io::Result<()>
     {
         use libc::{
 	    madvise,
 	    MADV_WILLNEED,
 	    MADV_DONTNEED
         };
+	let adv = adv.into();
+	if !adv.is_valid() {
+	    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Mutually exclusive advices in {adv:?}")));
+	}
         let (addr, len) = self.raw_parts();
-        match unsafe { madvise(addr as *mut _, len, adv.get_madv() | needed.map(|n| n.then(|| MADV_WILLNEED).unwrap_or(MADV_DONTNEED)).unwrap_or(0)) } {
-	    0 => Ok(()),
-	    _ => Err(io::Error::last_os_error())
-        }
+	if len == 0 {
+	    return Ok(());
+	}
+	let needed = needed.map(|n| if n { MADV_WILLNEED } else { MADV_DONTNEED });
+	for madv in adv.iter().map(Advice::get_madv).chain(needed) {
+            if unsafe { madvise(addr as *mut _, len, madv) } != 0 {
+		return Err(io::Error::last_os_error());
+            }
+	}
+	Ok(())
     }
 
-    /// With advice, used as a builder-pattern alternative for `advise()`.
+    /// Apply `madvise()` advice `madv` to the pages covering `range`.
     ///
-    /// # Returns
-    /// If `madvise()` fails, then the `io::Error` along with the previous instance is returned.
-    #[inline(always)] 
-    pub fn try_with_advice(mut self, adv: Advice, needed: Option<bool>) -> Result<Self, TryNewError<Self>>
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    fn madvise_range(&self, range: impl ops::RangeBounds<usize>, madv: libc::c_int) -> io::Result<()>
     {
-        match self.advise(adv, needed) {
-	    Ok(_) => Ok(self),
-	    Err(error) => Err(TryNewError {
-		error: Box::new(error),
-		value: self,
-	    })
+	let (addr, len) = self.raw_page_range(range);
+	if len == 0 {
+	    return Ok(());
+	}
+	if unsafe { libc::madvise(addr as *mut _, len, madv) } != 0 {
+	    return Err(io::Error::last_os_error());
 	}
+	Ok(())
     }
 
-    /// With advice, used as a builder-pattern alternative for `advise()`.
+    /// Change the memory protection of the whole mapping to `perm` via `mprotect()`.
+    ///
+    /// For a shared mapping, making it writable requires the file to have been opened for writing, and making it executable may be refused by the kernel for files on `noexec` mounts.
+    ///
+    /// # W^X
+    /// When the `strict-wxorx` feature is enabled, a `perm` that is both writable and executable (e.g. `Perm::WRX`) is rejected with an error of kind `PermissionDenied`, as in `try_new()`.
     ///
     /// # Returns
-    /// If `madvise()` fails, then the mapping is dropped and the error is returned. To keep the previous instance if the call failes, use `try_with_advice()`.
-    #[inline] 
-    pub fn with_advice(self, adv: Advice, needed: Option<bool>) -> io::Result<Self>
+    /// If `mprotect()` fails, then the error is returned and the protection is unchanged (e.g. `EACCES` when making a shared mapping of a read-only file writable.)
+    pub fn protect(&mut self, perm: Perm) -> io::Result<()>
     {
-	self.try_with_advice(adv, needed).map_err(Into::into)
+	check_wxorx(perm)?;
+	let prot = perm.get_prot();
+	let (addr, len) = self.raw_parts();
+	if len == 0 {
+	    return Ok(());
+	}
+	if unsafe { libc::mprotect(addr as *mut _, len, prot) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	Ok(())
     }
-    
-    /// Replace the inner file with another without checking static or dynamic bounding.
-    /// This function is extremely unsafe if the following conditions are not met in entirity.
+
+    /// Fault in every page of the mapping for reading, by reading one byte from each.
+    ///
+    /// Unlike `prefetch()`, which is only a hint, this guarantees the pages are resident (until the kernel reclaims them), e.g. before a latency-critical phase. Where it can be used, `MAP_POPULATE` does this at mapping time instead.
+    ///
+    /// # Note
+    /// This only faults the pages for reading: The first write to each page of a private mapping still faults to copy it. To fault pages for writing, use `touch_pages_mut()`.
+    pub fn touch_pages(&self)
+    {
+	let (addr, len) = self.raw_parts();
+	for offset in (0..len).step_by(get_page_size()) {
+	    std::hint::black_box(unsafe { ptr::read_volatile(addr.add(offset)) });
+	}
+    }
+
+    /// Fault in every page of the mapping for writing, by atomically adding `0` to one byte of each.
+    ///
+    /// The contents of the mapping are unchanged, even if another process writes to a shared mapping concurrently. For private mappings, each page is copied as it would be by a write.
+    ///
+    /// # Note
+    /// The mapping **must** be writable (e.g. `Perm::ReadWrite`), otherwise the process is killed by `SIGSEGV`. For read-only mappings, use `touch_pages()`.
+    pub fn touch_pages_mut(&mut self)
+    {
+	use std::sync::atomic::{AtomicU8, Ordering};
+	let (addr, len) = self.raw_parts();
+	for offset in (0..len).step_by(get_page_size()) {
+	    std::hint::black_box(unsafe { AtomicU8::from_ptr(addr.add(offset)) }.fetch_add(0, Ordering::Relaxed));
+	}
+    }
+
+    /// Tell the kernel the pages covering `range` will be accessed soon (`MADV_WILLNEED`), so it can start reading them in.
+    ///
+    /// The start of `range` is rounded down to the page it lies in. This is only a hint, and does not change the contents of the mapping.
+    ///
+    /// # Returns
+    /// If `madvise()` fails.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    #[inline]
+    pub fn prefetch(&self, range: impl ops::RangeBounds<usize>) -> io::Result<()>
+    {
+	self.madvise_range(range, libc::MADV_WILLNEED)
+    }
+
+    /// Drop the resident pages covering `range` (`MADV_DONTNEED`), releasing the memory they use.
+    ///
+    /// The start of `range` is rounded down to the page it lies in. The next access to an evicted page faults it in again:
+    /// * A shared file-backed page is re-read from the file. Changes already written to it are kept, as they are in the page cache.
+    /// * A private page is re-read from the file, or zero-filled for an anonymous mapping.
+    ///
+    /// # Data loss
+    /// Changes to *private* pages exist only in the evicted pages, so they are discarded. For shared mappings, `flush_and_evict()` makes sure changes reach the file before the pages are dropped.
+    ///
+    /// # Returns
+    /// If `madvise()` fails.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds of the mapping.
+    #[inline]
+    pub fn evict(&mut self, range: impl ops::RangeBounds<usize>) -> io::Result<()>
+    {
+	self.madvise_range(range, libc::MADV_DONTNEED)
+    }
+
+    /// Move the mapping to the address `addr` via `mremap()`.
+    ///
+    /// The mapped memory keeps its contents, and the region it was previously mapped at is unmapped by the move.
+    ///
+    /// # Returns
+    /// If `mremap()` fails, then the error is returned and the mapping is left where it was.
+    ///
+    /// # Safety
+    /// * `addr` **must** be page-aligned.
+    /// * Any existing mapping in the range `addr..addr+len()` is replaced. If anything else refers to memory in that range, this is undefined behaviour.
+    ///   To reserve a range for this purpose, map it first with `MAP_FIXED_NOREPLACE`.
+    pub unsafe fn remap_fixed(&mut self, addr: *mut libc::c_void) -> io::Result<()>
+    {
+	let (old, len) = self.raw_parts();
+	match libc::mremap(old as *mut _, len, len, libc::MREMAP_FIXED | libc::MREMAP_MAYMOVE, addr) {
+	    MAP_FAILED => Err(io::Error::last_os_error()),
+	    ptr => {
+		let mem = match NonNull::new(ptr as *mut u8) {
+		    Some(n) => n,
+		    _ => _panic_invalid_address(),
+		};
+		// The old region is no longer mapped, so it must not be unmapped on drop. Any guard pages around it are left behind, so unmap those now.
+		let guard = self.map.1;
+		if guard != 0 {
+		    libc::munmap(old.sub(guard) as *mut _, round_up_to_page(len) + guard * 2);
+		}
+		mem::forget(mem::replace(&mut self.map, MappedSlice(UniqueSlice {
+		    mem,
+		    end: NonNull::new_unchecked(mem.as_ptr().add(len)),
+		}, 0)));
+		Ok(())
+	    },
+	}
+    }
+
+    /// Detach the mapping from its backing file, so it becomes private anonymous memory holding the same contents at the same address.
+    ///
+    /// A new `MAP_PRIVATE | MAP_ANONYMOUS` region is mapped, the current contents are copied into it, and it is then moved over the original address with `mremap()`, replacing it in one step.
+    /// After this, truncating or changing the file no longer affects the mapping (e.g. there is no risk of `SIGBUS`), and changes to the mapping are never written back to the file.
+    ///
+    /// # Note
+    /// This is a copy: it touches every page of the mapping, and needs enough memory for all of it. The file `T` is kept, but is no longer related to the mapping, and flushing it does nothing useful.
+    /// The new memory is mapped readable and writable regardless of the original `Perm`. Use `protect()` afterwards to restrict it again.
+    ///
+    /// # Returns
+    /// If mapping or moving the new region fails, then the error is returned and the original mapping is left untouched.
+    ///
+    /// # Panics
+    /// If `mmap()` succeeds, but returns an invalid address (e.g. 0)
+    pub fn detach_backing(&mut self) -> io::Result<()>
+    {
+	let (old, len) = self.raw_parts();
+	if len == 0 {
+	    return Ok(());
+	}
+	unsafe {
+	    let new = match mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) {
+		MAP_FAILED => return Err(io::Error::last_os_error()),
+		ptr if ptr.is_null() => _panic_invalid_address(),
+		ptr => ptr,
+	    };
+	    ptr::copy_nonoverlapping(old as *const u8, new as *mut u8, len);
+	    // Atomically replaces the original pages; `self.map` keeps referring to the same address range.
+	    if libc::mremap(new, len, len, libc::MREMAP_FIXED | libc::MREMAP_MAYMOVE, old as *mut _) == MAP_FAILED {
+		let err = io::Error::last_os_error();
+		libc::munmap(new, len);
+		return Err(err);
+	    }
+	}
+	Ok(())
+    }
+
+    /// Set the NUMA memory policy of the mapping to `mode` over the node set `nodes` via `mbind()`.
+    ///
+    /// # Note
+    /// The policy only affects pages that have not yet been faulted in. To also move pages that are already resident onto the new node set, use `migrate_numa()`.
+    ///
+    /// # Returns
+    /// If `mbind()` fails, then the error is returned unchanged (e.g. `EINVAL` for an empty node set with a policy that requires one, or `EPERM`.)
+    #[inline]
+    pub fn bind_numa(&mut self, nodes: &[u32], mode: NumaPolicy) -> io::Result<()>
+    {
+	self.mbind(nodes, mode, 0)
+    }
+
+    /// Set the NUMA memory policy of the mapping like `bind_numa()`, and also move any already-faulted pages to conform to it (`MPOL_MF_MOVE`.)
+    ///
+    /// # Returns
+    /// If `mbind()` fails, then the error is returned unchanged. `EIO` is returned if some pages could not be moved.
+    #[inline]
+    pub fn migrate_numa(&mut self, nodes: &[u32], mode: NumaPolicy) -> io::Result<()>
+    {
+	/// Not exported by `libc`; from `<linux/mempolicy.h>`.
+	const MPOL_MF_MOVE: libc::c_uint = 1 << 1;
+	self.mbind(nodes, mode, MPOL_MF_MOVE)
+    }
+
+    /// Interleave the mapping's pages across every online NUMA node (`MPOL_INTERLEAVE`), for bandwidth-bound workloads.
+    ///
+    /// The online nodes are read from `/sys/devices/system/node/online`.
+    ///
+    /// # Note
+    /// As with `bind_numa()`, the policy only affects pages that have not yet been faulted in.
+    ///
+    /// # Returns
+    /// If the online node list cannot be read or parsed, then the error is returned.
+    /// If `mbind()` fails, then the error is returned unchanged.
+    pub fn interleave_all(&mut self) -> io::Result<()>
+    {
+	let nodes = parse_node_list(&std::fs::read_to_string("/sys/devices/system/node/online")?)?;
+	self.mbind(&nodes, NumaPolicy::Interleave, 0)
+    }
+
+    fn mbind(&mut self, nodes: &[u32], mode: NumaPolicy, flags: libc::c_uint) -> io::Result<()>
+    {
+	use libc::c_ulong;
+	const BITS: usize = c_ulong::BITS as usize;
+
+	let mut mask: Vec<c_ulong> = match nodes.iter().max() {
+	    Some(&max) => vec![0; (max as usize / BITS) + 1],
+	    None => Vec::new(),
+	};
+	for &node in nodes {
+	    let node = node as usize;
+	    mask[node / BITS] |= 1 << (node % BITS);
+	}
+	// The kernel reads `maxnode - 1` bits of the mask.
+	let (mask_ptr, maxnode) = if mask.is_empty() {
+	    (ptr::null(), 0)
+	} else {
+	    (mask.as_ptr(), (mask.len() * BITS) as c_ulong + 1)
+	};
+
+	let (addr, len) = self.raw_parts();
+	if len == 0 {
+	    return Ok(());
+	}
+	if unsafe { libc::syscall(libc::SYS_mbind, addr, len, mode.get_mpol() as c_ulong, mask_ptr, maxnode, flags) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	Ok(())
+    }
+
+    /// With advice, used as a builder-pattern alternative for `advise()`.
+    ///
+    /// # Returns
+    /// If `madvise()` fails, then the `io::Error` along with the previous instance is returned.
+    #[inline(always)] 
+    pub fn try_with_advice(mut self, adv: impl Into<AdviceSet>, needed: Option<bool>) -> Result<Self, TryNewError<Self>>
+    {
+        match self.advise(adv, needed) {
+	    Ok(_) => Ok(self),
+	    Err(error) => Err(TryNewError {
+		error: Box::new(error),
+		value: self,
+	    })
+	}
+    }
+
+    /// With advice, used as a builder-pattern alternative for `advise()`.
+    ///
+    /// # Returns
+    /// If `madvise()` fails, then the mapping is dropped and the error is returned. To keep the previous instance if the call failes, use `try_with_advice()`.
+    #[inline] 
+    pub fn with_advice(self, adv: impl Into<AdviceSet>, needed: Option<bool>) -> io::Result<Self>
+    {
+	self.try_with_advice(adv, needed).map_err(Into::into)
+    }
+
+    /// Apply advice with `advise()` and return the mapping, for chaining several advices during construction.
+    ///
+    /// ```
+    /// # use mapped_file::*;
+    /// # fn main() -> std::io::Result<()> {
+    /// # let file = mapped_file::file::memory::MemoryFile::with_size(4096)?;
+    /// let map = MappedFile::new(file, 4096, Perm::Readonly, Flags::Shared)?
+    ///     .advising(Advice::Sequential, Some(true))?
+    ///     .advising(Advice::DontDump, None)?;
+    /// # drop(map); Ok(()) }
+    /// ```
+    ///
+    /// # Returns
+    /// If `madvise()` fails, then the mapping is dropped and the error is returned. To keep the mapping on failure, use `try_with_advice()`.
+    #[inline]
+    pub fn advising(mut self, adv: impl Into<AdviceSet>, needed: Option<bool>) -> io::Result<Self>
+    {
+	self.advise(adv, needed)?;
+	Ok(self)
+    }
+    
+    /// Replace the inner file with another without checking static or dynamic bounding.
+    /// This function is extremely unsafe if the following conditions are not met in entirity.
     ///
     /// # Safety
     /// * `U` and `T` **must** have an `AsRawFd::as_raw_fd()` impl that returns the same `RawFd` value unconditionally.
@@ -452,256 +1206,1805 @@ impl<T> MappedFile<T> {
     #[inline(always)]
     pub unsafe fn replace_inner_unchecked<U>(self, other: U) -> (MappedFile<U>, T)
     {
-        let MappedFile{ file, map } = self;
-        (MappedFile {
-	    file: other,
-	    map
-        }, file)
+        let MappedFile{ file, map } = self;
+        (MappedFile {
+	    file: other,
+	    map
+        }, file)
+    }
+
+    /// The size of the mapped memory
+    #[inline]
+    pub fn len(&self) -> usize
+    {
+        self.map.0.len()
+    }
+
+    /// The base address of the mapped memory, as an integer.
+    #[inline]
+    pub fn address(&self) -> usize
+    {
+	self.map.0.mem.as_ptr() as usize
+    }
+
+    /// The alignment of the base address of the mapped memory: the largest power of two that divides `address()`.
+    ///
+    /// This is always at least the page size (see `get_page_size()`), but may be larger (e.g. for huge-page mappings.)
+    #[inline]
+    pub fn alignment(&self) -> usize
+    {
+	1 << self.address().trailing_zeros()
+    }
+
+    /// Get a slice of the mapped memory
+    #[inline]
+    pub fn as_slice(&self) -> &[u8]
+    {
+        &self.map.0[..]
+    }
+
+    /// Get a mutable slice of the mapped memory
+    #[inline]
+    pub fn as_slice_mut(&mut self) -> &mut [u8]
+    {
+        &mut self.map.0[..]
+    }
+
+    /// Get the mapped memory as a string slice, without copying it.
+    ///
+    /// # Returns
+    /// If the mapped memory is not valid UTF-8, the `Utf8Error` describing where it is invalid is returned.
+    ///
+    /// # Note
+    /// The whole mapping is validated on each call. To skip validation of data already known to be valid, use `as_str_unchecked()`.
+    #[inline]
+    pub fn as_str(&self) -> Result<&str, std::str::Utf8Error>
+    {
+	std::str::from_utf8(self.as_slice())
+    }
+
+    /// Get the mapped memory as a string slice, without copying or validating it.
+    ///
+    /// # Safety
+    /// The mapped memory **must** be valid UTF-8 for the lifetime of the returned reference. (Note that a shared mapping can be changed by other processes.)
+    #[inline]
+    pub unsafe fn as_str_unchecked(&self) -> &str
+    {
+	std::str::from_utf8_unchecked(self.as_slice())
+    }
+
+    /// Get a raw slice of the mapped memory
+    #[inline] 
+    pub fn as_raw_slice(&self) -> *const [u8]
+    {
+	self.map.0.as_raw_slice()
+    }
+
+    /// Get a raw mutable slice of the mapped memory
+    #[inline]
+    pub fn as_raw_slice_mut(&mut self) -> *mut [u8]
+    {
+	self.map.0.as_raw_slice_mut()
+    }
+
+    /// Get the byte at `idx` in the mapped memory.
+    ///
+    /// # Returns
+    /// If `idx` is out of bounds of the mapping, `None` is returned.
+    ///
+    /// # Note
+    /// This shadows `<[u8]>::get()`. To index by a range, use `as_slice().get()`.
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<u8>
+    {
+	self.as_slice().get(idx).copied()
+    }
+
+    /// Set the byte at `idx` in the mapped memory to `val`.
+    ///
+    /// # Returns
+    /// If `idx` is out of bounds of the mapping, an `OutOfBounds` error is returned and nothing is written.
+    #[inline]
+    pub fn set(&mut self, idx: usize, val: u8) -> Result<(), OutOfBounds>
+    {
+	let len = self.len();
+	match self.as_slice_mut().get_mut(idx) {
+	    Some(byte) => {
+		*byte = val;
+		Ok(())
+	    },
+	    None => Err(OutOfBounds {
+		index: idx,
+		len,
+	    }),
+	}
+    }
+
+    /// Copy `data` into the start of the mapped memory.
+    ///
+    /// If `strict` is `false`, only as much of `data` as fits into the mapping is copied. Otherwise, `data` must fit entirely.
+    ///
+    /// # Returns
+    /// The number of bytes copied.
+    /// If `strict` is `true` and `data` is longer than the mapping, an `OutOfBounds` error (whose index is the first byte that does not fit) is returned and nothing is written.
+    #[inline]
+    pub fn copy_from(&mut self, data: &[u8], strict: bool) -> Result<usize, OutOfBounds>
+    {
+	let len = self.len();
+	if strict && data.len() > len {
+	    return Err(OutOfBounds {
+		index: len,
+		len,
+	    });
+	}
+	self.write_at(0, data)
+    }
+
+    /// Copy as much of `data` as fits into the mapped memory starting at `offset`.
+    ///
+    /// # Returns
+    /// The number of bytes copied, which is less than `data.len()` if it runs past the end of the mapping.
+    /// If `offset` is past the end of the mapping, an `OutOfBounds` error is returned and nothing is written.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, OutOfBounds>
+    {
+	let len = self.len();
+	let Some(dest) = self.as_slice_mut().get_mut(offset..) else {
+	    return Err(OutOfBounds {
+		index: offset,
+		len,
+	    });
+	};
+	let n = data.len().min(dest.len());
+	dest[..n].copy_from_slice(&data[..n]);
+	Ok(n)
+    }
+
+    /// Overwrite the mapped memory with `new_contents`, copying only the pages whose contents differ.
+    ///
+    /// Each page of the mapping is compared against the corresponding part of `new_contents` first, so pages that are already up to date are only read, never written. For a mostly-identical update, this keeps the number of dirtied pages (and so the work of a later `flush()`, or the copy-on-write faults of a private mapping) down to the pages that actually changed.
+    ///
+    /// # Returns
+    /// The number of pages that were written to.
+    ///
+    /// # Panics
+    /// If `new_contents.len()` is not equal to `len()`.
+    pub fn update_from(&mut self, new_contents: &[u8]) -> usize
+    {
+	assert_eq!(new_contents.len(), self.len(), "update_from(): new contents must be the same length as the mapping");
+	let page = get_page_size();
+	let mut changed = 0;
+	for (current, new) in self.as_slice_mut().chunks_mut(page).zip(new_contents.chunks(page)) {
+	    if current != new {
+		current.copy_from_slice(new);
+		changed += 1;
+	    }
+	}
+	changed
+    }
+
+    /// Copy as many bytes as fit into `buf` out of the mapped memory, starting at `offset`.
+    ///
+    /// # Returns
+    /// The number of bytes copied: `buf.len()`, or fewer if the mapping ends first.
+    /// If `offset` is at or past the end of the mapping, `0` is returned.
+    #[inline]
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize
+    {
+	let Some(src) = self.as_slice().get(offset..) else {
+	    return 0;
+	};
+	let n = buf.len().min(src.len());
+	buf[..n].copy_from_slice(&src[..n]);
+	n
+    }
+
+    /// An iterator over `size`-byte chunks of the mapped memory. The last chunk may be shorter.
+    ///
+    /// # Panics
+    /// If `size` is 0.
+    #[inline]
+    pub fn chunks(&self, size: usize) -> std::slice::Chunks<'_, u8>
+    {
+	self.as_slice().chunks(size)
+    }
+
+    /// An iterator over mutable `size`-byte chunks of the mapped memory. The last chunk may be shorter.
+    ///
+    /// # Panics
+    /// If `size` is 0.
+    #[inline]
+    pub fn chunks_mut(&mut self, size: usize) -> std::slice::ChunksMut<'_, u8>
+    {
+	self.as_slice_mut().chunks_mut(size)
+    }
+
+    /// An iterator over each page of the mapped memory. (see `get_page_size()`.)
+    ///
+    /// # Note
+    /// For huge-page mappings, this still iterates over the system's regular page size.
+    #[inline]
+    pub fn pages(&self) -> std::slice::Chunks<'_, u8>
+    {
+	self.chunks(get_page_size())
+    }
+
+    /// An iterator over each mutable page of the mapped memory. (see `pages()`.)
+    #[inline]
+    pub fn pages_mut(&mut self) -> std::slice::ChunksMut<'_, u8>
+    {
+	self.chunks_mut(get_page_size())
+    }
+
+    /// Feed the mapped memory into `hasher`.
+    #[inline]
+    pub fn checksum<H: std::hash::Hasher + ?Sized>(&self, hasher: &mut H)
+    {
+	hasher.write(self.as_slice())
+    }
+
+    /// Compute the CRC32 checksum of the mapped memory.
+    #[cfg(feature="checksum")]
+    #[inline]
+    pub fn crc32(&self) -> u32
+    {
+	crc32fast::hash(self.as_slice())
+    }
+
+    /// Feed the mapped memory into `digest`, page by page. The caller then finalizes the digest.
+    ///
+    /// # Note
+    /// Before reading, the kernel is advised that the mapping will be accessed sequentially (`Advice::Sequential`), so pages can be prefetched.
+    /// This advice remains on the mapping afterwards. If `madvise()` fails, it is ignored.
+    #[cfg(feature="digest")]
+    pub fn hash_into<D: digest::Digest>(&self, digest: &mut D)
+    {
+	let (addr, len) = self.raw_parts();
+	unsafe {
+	    libc::madvise(addr as *mut _, len, Advice::Sequential.get_madv());
+	}
+	for page in self.pages() {
+	    digest.update(page);
+	}
+    }
+
+    /// Write the whole of the mapped memory to `w`.
+    ///
+    /// The kernel is advised the mapping will be read sequentially (`MADV_SEQUENTIAL`) first, and it is then written in chunks of at most 1MB, so faulting in pages is interleaved with writing them out.
+    ///
+    /// # Returns
+    /// The number of bytes written, which is always `len()`.
+    /// If writing fails, then the error is returned. Part of the mapping may have been written.
+    pub fn write_to<W: io::Write + ?Sized>(&self, w: &mut W) -> io::Result<usize>
+    {
+	const CHUNK: usize = 1024 * 1024;
+	// Only a hint, so failure is ignored.
+	let _ = self.madvise_range(.., libc::MADV_SEQUENTIAL);
+	for chunk in self.as_slice().chunks(CHUNK) {
+	    w.write_all(chunk)?;
+	}
+	Ok(self.len())
+    }
+
+    /// Read from `r` into the mapped memory, from its start, until it is full or `r` reaches its end.
+    ///
+    /// Short reads (e.g. from a pipe or socket) are retried, and reading stops exactly at `len()`: No more than `len()` bytes are consumed from `r`. If `r` ends first, the rest of the mapping is left unchanged.
+    ///
+    /// # Returns
+    /// The number of bytes read.
+    /// If reading fails, then the error is returned. Bytes read before the failure remain in the mapping.
+    pub fn fill_from<R: io::Read + ?Sized>(&mut self, r: &mut R) -> io::Result<usize>
+    {
+	let buf = self.as_slice_mut();
+	let mut filled = 0;
+	while filled < buf.len() {
+	    match r.read(&mut buf[filled..]) {
+		Ok(0) => break,
+		Ok(n) => filled += n,
+		Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+		Err(e) => return Err(e),
+	    }
+	}
+	Ok(filled)
+    }
+
+    /// Format the mapped memory in the canonical `hexdump -C` style.
+    ///
+    /// # Note
+    /// Formatting the returned value reads the whole mapping; this can fault in every page.
+    #[inline]
+    pub fn hexdump(&self) -> impl fmt::Display + '_
+    {
+	Hexdump(self.as_slice())
+    }
+
+    /// Checks if the mapping dangles (i.e. `len() == 0`.)
+    #[inline]
+    pub fn is_empty(&self) -> bool
+    {
+        self.map.0.is_empty()
+    }
+
+    /// Check that the mapped memory can be viewed as a slice of `U`, and get the number of elements in that slice.
+    fn slice_of_len<U>(&self) -> Result<usize, AlignError>
+    {
+	let (addr, len) = self.raw_parts();
+	let size = mem::size_of::<U>();
+	let required = mem::align_of::<U>();
+	let size_mismatch = size == 0 || !len.is_multiple_of(size);
+	if size_mismatch || !(addr as usize).is_multiple_of(required) {
+	    Err(AlignError {
+		required,
+		actual_addr: addr as usize,
+		size_mismatch,
+	    })
+	} else {
+	    Ok(len / size)
+	}
+    }
+
+    /// Get a slice of the mapped memory as elements of type `U`.
+    ///
+    /// # Returns
+    /// If the mapping is not aligned to `U`, or its length is not a multiple of the size of `U`, then an `AlignError` is returned.
+    /// Mappings created by `mmap()` are page-aligned, so this will usually only fail for the latter reason.
+    /// A zero-sized `U` always fails.
+    ///
+    /// # Safety
+    /// Any bit pattern in the mapped memory **must** be a valid value of `U` (e.g. primitive integers, or `#[repr(C)]` aggregates of them.)
+    #[inline]
+    pub unsafe fn as_slice_of<U>(&self) -> Result<&[U], AlignError>
+    {
+	let len = self.slice_of_len::<U>()?;
+	Ok(std::slice::from_raw_parts(self.map.0.mem.as_ptr() as *const U, len))
+    }
+
+    /// Get a mutable slice of the mapped memory as elements of type `U`.
+    ///
+    /// # Returns
+    /// See `as_slice_of()`.
+    ///
+    /// # Safety
+    /// Any bit pattern in the mapped memory **must** be a valid value of `U` (e.g. primitive integers, or `#[repr(C)]` aggregates of them.)
+    #[inline]
+    pub unsafe fn as_slice_of_mut<U>(&mut self) -> Result<&mut [U], AlignError>
+    {
+	let len = self.slice_of_len::<U>()?;
+	Ok(std::slice::from_raw_parts_mut(self.map.0.mem.as_ptr() as *mut U, len))
+    }
+
+    /// Query the kernel's page table entry for the page at `page_index` of the mapping, via `/proc/self/pagemap`.
+    ///
+    /// This is read-only introspection; it does not fault the page in.
+    ///
+    /// # Returns
+    /// If `page_index` is not a page of the mapping, an `OutOfBounds` error (with the index and length counted in pages) is returned.
+    /// If `/proc/self/pagemap` cannot be opened or read, then that error is returned.
+    ///
+    /// # Note
+    /// The kernel only reports the PFN to processes with `CAP_SYS_ADMIN`; otherwise `PagemapEntry::pfn` is `None` even for present pages.
+    pub fn pagemap(&self, page_index: usize) -> io::Result<PagemapEntry>
+    {
+	use std::os::unix::fs::FileExt;
+	let page = get_page_size();
+	let (addr, len) = self.raw_parts();
+	let pages = len.div_ceil(page);
+	if page_index >= pages {
+	    return Err(OutOfBounds { index: page_index, len: pages }.into());
+	}
+
+	let vpn = (addr as usize / page) + page_index;
+	let mut entry = [0u8; mem::size_of::<u64>()];
+	std::fs::File::open("/proc/self/pagemap")?.read_exact_at(&mut entry, (vpn * mem::size_of::<u64>()) as u64)?;
+	Ok(PagemapEntry::from_raw(u64::from_ne_bytes(entry)))
+    }
+
+    /// The number of bytes of the mapping currently resident in memory, as reported by `mincore()`.
+    ///
+    /// This is the number of resident pages multiplied by the page size (see `get_page_size()`), so the last page counts in full even if `len()` ends partway through it.
+    ///
+    /// # Note
+    /// For a file-backed mapping, `mincore()` reports pages present in the page cache, even if this process has not faulted them in yet. The result is only a snapshot: The kernel may page memory in or out at any time.
+    ///
+    /// # Returns
+    /// If `mincore()` fails.
+    pub fn rss(&self) -> io::Result<usize>
+    {
+	let page = get_page_size();
+	let (addr, len) = self.raw_parts();
+	if len == 0 {
+	    return Ok(0);
+	}
+	let mut vec = vec![0u8; len.div_ceil(page)];
+	if unsafe { libc::mincore(addr as *mut _, len, vec.as_mut_ptr()) } != 0 {
+	    return Err(io::Error::last_os_error());
+	}
+	// Only the lowest bit is defined; the others are reserved.
+	Ok(vec.iter().filter(|&&b| b & 1 != 0).count() * page)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MappedFile<T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	let (addr, len) = self.raw_parts();
+	f.debug_struct("MappedFile")
+	    .field("addr", &format_args!("{:#x}..{:#x}", addr as usize, addr as usize + len))
+	    .field("len", &len)
+	    .field("file", &self.file)
+	    .finish()
+    }
+}
+
+/// Formats a byte slice in the canonical `hexdump -C` style.
+struct Hexdump<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for Hexdump<'a>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	const WIDTH: usize = 16;
+	let mut previous: Option<&[u8]> = None;
+	let mut collapsed = false;
+	for (i, line) in self.0.chunks(WIDTH).enumerate() {
+	    // Repeated full lines are collapsed into a single `*`.
+	    if line.len() == WIDTH && previous == Some(line) {
+		if !collapsed {
+		    f.write_str("*\n")?;
+		    collapsed = true;
+		}
+		continue;
+	    }
+	    previous = Some(line);
+	    collapsed = false;
+
+	    write!(f, "{:08x}", i * WIDTH)?;
+	    for j in 0..WIDTH {
+		if j % 8 == 0 {
+		    f.write_str(" ")?;
+		}
+		match line.get(j) {
+		    Some(byte) => write!(f, " {byte:02x}")?,
+		    None => f.write_str("   ")?,
+		}
+	    }
+	    f.write_str("  |")?;
+	    for &byte in line {
+		let chr = if (0x20..0x7f).contains(&byte) { byte as char } else { '.' };
+		write!(f, "{chr}")?;
+	    }
+	    f.write_str("|\n")?;
+	}
+	if !self.0.is_empty() {
+	    writeln!(f, "{:08x}", self.0.len())?;
+	}
+	Ok(())
+    }
+}
+
+/// Parse a kernel node list (e.g. `0-3,5`), as found in `/sys/devices/system/node/online`.
+fn parse_node_list(list: &str) -> io::Result<Vec<u32>>
+{
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid node list {list:?}"));
+    let parse = |n: &str| n.parse::<u32>().map_err(|_| invalid());
+    let mut nodes = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+	match range.split_once('-') {
+	    Some((start, end)) => nodes.extend(parse(start)?..=parse(end)?),
+	    None => nodes.push(parse(range)?),
+	}
+    }
+    Ok(nodes)
+}
+
+/// Error returned when an access is out of the bounds of a mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
+pub struct OutOfBounds
+{
+    /// The offending index.
+    pub index: usize,
+    /// The length of the mapping.
+    pub len: usize,
+}
+
+impl error::Error for OutOfBounds{}
+
+impl fmt::Display for OutOfBounds
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	write!(f, "index {} is out of bounds for mapping of length {}", self.index, self.len)
+    }
+}
+
+impl From<OutOfBounds> for io::Error
+{
+    #[inline]
+    fn from(from: OutOfBounds) -> Self
+    {
+	io::Error::new(io::ErrorKind::InvalidInput, from)
+    }
+}
+
+/// Error returned when a mapping cannot be viewed as a slice of a type.
+///
+/// See `MappedFile::as_slice_of()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
+pub struct AlignError
+{
+    /// The alignment required by the type.
+    pub required: usize,
+    /// The address of the mapping.
+    pub actual_addr: usize,
+    /// If the length of the mapping is not a multiple of the size of the type.
+    pub size_mismatch: bool,
+}
+
+impl error::Error for AlignError{}
+
+impl fmt::Display for AlignError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	if self.size_mismatch {
+	    write!(f, "mapping at {:#x} cannot be viewed as a slice of type: length is not a multiple of the type's size", self.actual_addr)
+	} else {
+	    write!(f, "mapping at {:#x} cannot be viewed as a slice of type: address is not aligned to {} bytes", self.actual_addr, self.required)
+	}
+    }
+}
+
+/// An entry of `/proc/self/pagemap`, describing the physical backing of a single page.
+///
+/// See `MappedFile::pagemap()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
+pub struct PagemapEntry
+{
+    /// The page is resident in physical memory.
+    pub present: bool,
+    /// The page is swapped out.
+    pub swapped: bool,
+    /// The page is file-backed or shared-anonymous.
+    pub file_or_shared: bool,
+    /// The page frame number of a present page, if the kernel reported it.
+    pub pfn: Option<u64>,
+}
+
+impl PagemapEntry
+{
+    const PRESENT: u64 = 1 << 63;
+    const SWAPPED: u64 = 1 << 62;
+    const FILE_OR_SHARED: u64 = 1 << 61;
+    const PFN_MASK: u64 = (1 << 55) - 1;
+
+    /// Parse a raw 64-bit pagemap entry.
+    #[inline]
+    pub const fn from_raw(raw: u64) -> Self
+    {
+	let present = raw & Self::PRESENT != 0;
+	let pfn = raw & Self::PFN_MASK;
+	Self {
+	    present,
+	    swapped: raw & Self::SWAPPED != 0,
+	    file_or_shared: raw & Self::FILE_OR_SHARED != 0,
+	    // Unprivileged readers get a PFN of zero.
+	    pfn: if present && pfn != 0 { Some(pfn) } else { None },
+	}
+    }
+}
+
+/// Error returned when mapping operation fails.
+///
+/// Also returns the value passed in.
+pub struct TryNewError<T: ?Sized>
+{
+    error: Box<io::Error>,
+    value: T
+}
+
+impl<T:?Sized> error::Error for TryNewError<T>
+{
+    #[inline] 
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+	Some(self.error.as_ref())
+    }
+}
+
+impl<T:?Sized> fmt::Display for TryNewError<T>
+{
+    /// The inner error is included in the message, as well as being the `source()`, since it is often all a caller needs.
+    #[inline] 
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	write!(f, "error in mapping of type {}: {}", std::any::type_name::<T>(), self.error)
+    }
+}
+
+impl<T:?Sized> fmt::Debug for TryNewError<T>
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	f.debug_struct("TryNewError")
+	    .field("error", &self.error)
+	    .finish_non_exhaustive()
+    }
+}
+
+impl<T: ?Sized> TryNewError<T>
+{
+    /// A reference to the value
+    #[inline] 
+    pub fn value(&self) -> &T
+    {
+	&self.value
+    }
+    /// A mutable reference to the value
+    #[inline] 
+    pub fn value_mut(&mut self) -> &mut T
+    {
+	&mut self.value
+    }
+    /// A reference to the IO error
+    #[inline] 
+    pub fn error(&self) -> &io::Error
+    {
+	&self.error
+    }
+    /// Consume a boxed instance and return the boxed IO error.
+    #[inline] 
+    pub fn into_error_box(self: Box<Self>) -> Box<io::Error>
+    {
+	self.error
+    }
+}
+
+impl<T> TryNewError<T>
+{
+    #[inline] 
+    fn wrap_last_error(value: T) -> Self
+    {
+	Self {
+	    error: Box::new(io::Error::last_os_error()),
+	    value,
+	}
+    }
+
+    #[inline] 
+    fn wrap((error, value): (impl Into<io::Error>, T)) -> Self
+    {
+	Self {
+	    error: Box::new(error.into()),
+	    value
+	}
+    }
+    /// Consume into the contained value 
+    #[inline] 
+    pub fn into_inner(self) -> T
+    {
+	self.value
+    }
+
+    /// Consume into the IO error
+    #[inline] 
+    pub fn into_error(self) -> io::Error
+    {
+	*self.error
+    }
+    /// Consume into the value and the error.
+    #[inline] 
+    pub fn into_parts(self) -> (T, io::Error)
+    {
+	(self.value, *self.error)
+    }
+
+    /// Consume into an IO error whose message describes both the type of the mapping and the underlying error.
+    ///
+    /// Unlike `into_error()` (and the `From` impl,) the context of which mapping failed is kept, which makes errors propagated with `?` more informative.
+    /// The error kind is kept, but the raw OS error code is not: Use `into_error()` if it needs to be inspected.
+    #[inline]
+    pub fn into_io_with_context(self) -> io::Error
+    {
+	io::Error::new(self.error.kind(), self.to_string())
+    }
+
+    /// Transform the contained value with `f`, keeping the error.
+    #[inline]
+    pub fn map_value<U>(self, f: impl FnOnce(T) -> U) -> TryNewError<U>
+    {
+	TryNewError {
+	    error: self.error,
+	    value: f(self.value),
+	}
+    }
+}
+
+impl<T: ?Sized> From<Box<TryNewError<T>>> for io::Error
+{
+    #[inline] 
+    fn from(from: Box<TryNewError<T>>) -> Self
+    {
+	*from.error
+    }
+}
+
+impl<T> From<TryNewError<T>> for io::Error
+{
+    #[inline] 
+    fn from(from: TryNewError<T>) -> Self
+    {
+	from.into_error()
+    }
+}
+
+impl<T: AsRawFd> Borrow<T> for MappedFile<T>
+{
+    #[inline]
+    fn borrow(&self) -> &T
+    {
+        &self.file
+    }
+}
+
+impl<T> Borrow<[u8]> for MappedFile<T>
+{
+    #[inline]
+    fn borrow(&self) -> &[u8]
+    {
+        self.as_slice()
+    }
+}
+
+impl<T> BorrowMut<[u8]> for MappedFile<T>
+{
+    #[inline]
+    fn borrow_mut(&mut self) -> &mut [u8]
+    {
+        self.as_slice_mut()
+    }
+}
+
+impl<T> AsRef<[u8]> for MappedFile<T>
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8]
+    {
+	self.as_slice()
+    }
+}
+
+impl<T> AsMut<[u8]> for MappedFile<T>
+{
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8]
+    {
+	self.as_slice_mut()
+    }
+}
+
+impl<T> ops::Deref for MappedFile<T>
+{
+    type Target=  [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target
+    {
+        self.as_slice()
+    }
+}
+impl<T> ops::DerefMut for MappedFile<T>
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target
+    {
+        self.as_slice_mut()
+    }
+}
+
+/// Used for anonymous mappings with `MappedFile`.
+///
+/// # Safety
+/// The `AsRawFd` impl of this structure always returns `-1`. It should only be used with `MappedFile`, as this is an invlalid file descriptor in all other contexts.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
+pub struct Anonymous;
+
+impl AsRawFd for Anonymous
+{
+    #[inline(always)] 
+    fn as_raw_fd(&self) -> RawFd {
+	-1
+    }
+}
+
+/// Used for anonymous mappings backed by huge-pages of the contained size with `MappedFile`. See `MappedFile::anonymous_from()`.
+///
+/// # Safety
+/// The `AsRawFd` impl of this structure always returns `-1`, as with `Anonymous`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
+pub struct AnonymousHuge(pub MapHugeFlag);
+
+impl AsRawFd for AnonymousHuge
+{
+    #[inline(always)] 
+    fn as_raw_fd(&self) -> RawFd {
+	-1
+    }
+}
+
+/// A marker type for an anonymous mapping, which provides the extra `mmap()` flags the mapping needs. See `MappedFile::anonymous_from()`.
+///
+/// # Safety
+/// `as_raw_fd()` must return `-1`, and `get_anonymous_flags()` must only return flags valid for a `MAP_PRIVATE | MAP_ANONYMOUS` mapping.
+pub unsafe trait AnonymousMarker: AsRawFd
+{
+    /// The flags to add to `MAP_PRIVATE | MAP_ANONYMOUS`.
+    fn get_anonymous_flags(&self) -> libc::c_int;
+}
+
+unsafe impl AnonymousMarker for Anonymous
+{
+    #[inline(always)]
+    fn get_anonymous_flags(&self) -> libc::c_int {
+	0
+    }
+}
+
+unsafe impl AnonymousMarker for AnonymousHuge
+{
+    #[inline(always)]
+    fn get_anonymous_flags(&self) -> libc::c_int {
+	self.0.mmap_flags()
+    }
+}
+
+impl<A: AnonymousMarker> MappedFile<A>
+{
+    /// Create a `MAP_PRIVATE | MAP_ANONYMOUS` mapping of `len` bytes with memory protection as provided by `perm`, and any extra flags required by `marker`.
+    ///
+    /// For `AnonymousHuge`, `MAP_HUGETLB` and the encoded huge-page size are added, so the mapping is backed by huge-pages of that size.
+    ///
+    /// # Note
+    /// For huge-page mappings, `len` should be a multiple of the huge-page size. The kernel must also have huge-pages of the size reserved (see `/proc/sys/vm/nr_hugepages`), or `mmap()` fails with `ENOMEM`.
+    ///
+    /// # Returns
+    /// If `mmap()` fails.
+    #[inline]
+    pub fn anonymous_from(marker: A, len: usize, perm: Perm) -> io::Result<Self>
+    {
+	struct MarkerFlags(libc::c_int);
+	unsafe impl MapFlags for MarkerFlags
+	{
+	    #[inline(always)]
+	    fn get_mmap_flags(&self) -> libc::c_int {
+		libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | self.0
+	    }
+	}
+	let flags = MarkerFlags(marker.get_anonymous_flags());
+	Self::new(marker, len, perm, flags)
+    }
+}
+
+impl MappedFile<Anonymous>
+{
+    /// Create a `MAP_SHARED | MAP_ANONYMOUS` mapping of `len` bytes with memory protection as provided by `perm`.
+    ///
+    /// The mapping is not backed by any file, but is shared with child processes created by `fork()` after this call: Writes from either the parent or the child are visible to the other.
+    /// Unlike a `file::memory::MemoryFile` mapping, no file descriptor is needed (or needs to be `dup()`'d) to share it.
+    ///
+    /// # Note
+    /// The mapping does not survive `exec()`.
+    ///
+    /// # Returns
+    /// If `mmap()` fails.
+    #[inline]
+    pub fn shared_anonymous(len: usize, perm: Perm) -> io::Result<Self>
+    {
+	struct SharedAnonymous;
+	unsafe impl MapFlags for SharedAnonymous
+	{
+	    #[inline(always)]
+	    fn get_mmap_flags(&self) -> libc::c_int {
+		libc::MAP_SHARED | libc::MAP_ANONYMOUS
+	    }
+	}
+	Self::new(Anonymous, len, perm, SharedAnonymous)
+    }
+
+    /// Create a readable and writable `MAP_SHARED | MAP_ANONYMOUS` mapping of `len` bytes, for sharing memory with child processes created by `fork()`.
+    ///
+    /// This is `shared_anonymous(len, Perm::ReadWrite)`. Both processes see the same physical pages, so writes made by either after the `fork()` are visible to the other.
+    /// Access to the memory must be synchronised between the processes, e.g. with atomics as below: A lock-free atomic in shared memory works across processes the same way it does across threads.
+    ///
+    /// # Example
+    /// A counter incremented by both a parent and its child:
+    /// ```
+    /// # use mapped_file::*;
+    /// # fn main() -> std::io::Result<()> {
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// let map = MappedFile::fork_shared(std::mem::size_of::<AtomicU64>())?;
+    /// // SAFETY: The mapping is page-aligned, zero-filled, and only ever accessed atomically.
+    /// let counter = unsafe { &*(map.as_ptr() as *const AtomicU64) };
+    ///
+    /// match unsafe { libc::fork() } {
+    ///     -1 => return Err(std::io::Error::last_os_error()),
+    ///     0 => {
+    ///         counter.fetch_add(1, Ordering::SeqCst);
+    ///         // Do not run the parent's destructors or exit handlers in the child.
+    ///         unsafe { libc::_exit(0) }
+    ///     },
+    ///     child => {
+    ///         counter.fetch_add(1, Ordering::SeqCst);
+    ///         unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) };
+    ///     },
+    /// }
+    /// assert_eq!(counter.load(Ordering::SeqCst), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Note
+    /// The child should only do async-signal-safe work if the parent is multi-threaded, and should leave with `libc::_exit()` rather than returning. The child's copy of the `MappedFile` is never dropped then, which is fine: The mapping goes away with the process.
+    ///
+    /// # Returns
+    /// If `mmap()` fails.
+    #[inline]
+    pub fn fork_shared(len: usize) -> io::Result<Self>
+    {
+	Self::shared_anonymous(len, Perm::ReadWrite)
+    }
+
+    /// Map multiple files back-to-back into one contiguous region of memory.
+    ///
+    /// Each segment `(fd, len)` maps the first `len` bytes of `fd`, directly after the previous segment, with memory protection as provided by `perm`, and mapping flags provided by `flags`.
+    /// A `PROT_NONE` region of the total length is reserved first, and each segment is then mapped over it with `MAP_FIXED`.
+    ///
+    /// The returned mapping owns the whole region: When it is dropped, every segment is unmapped at once. The file descriptors are not owned, and may be closed after this call.
+    ///
+    /// # Note
+    /// Every segment's `len`, except the last's, **must** be a multiple of the page size (see `get_page_size()`) so that the following segment starts on a page boundary.
+    ///
+    /// # Returns
+    /// If a segment's length is not a multiple of the page size, the total length overflows, or the total length is `0`, an error of kind `InvalidInput` is returned.
+    /// When the `strict-wxorx` feature is enabled, a `perm` that is both writable and executable is rejected with an error of kind `PermissionDenied`, as in `try_new()`.
+    /// If any `mmap()` fails, then the error is returned and the whole region is unmapped.
+    ///
+    /// # Panics
+    /// If `mmap()` succeeds, but returns an invalid address (e.g. 0)
+    pub fn concat(segments: &[(RawFd, usize)], perm: Perm, flags: impl MapFlags) -> io::Result<Self>
+    {
+	const NULL: *mut libc::c_void = ptr::null_mut();
+	check_wxorx(perm)?;
+	let page = get_page_size();
+	let mut total = 0usize;
+	for (i, &(_, len)) in segments.iter().enumerate() {
+	    if i + 1 < segments.len() && !len.is_multiple_of(page) {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Segment {i} length {len} is not a multiple of the page size {page}")));
+	    }
+	    total = total.checked_add(len).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Total length of segments overflows"))?;
+	}
+	if total == 0 {
+	    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cannot map an empty set of segments"));
+	}
+
+	// Reserve the whole region, this mapping is what is unmapped on drop.
+	let mut root = match unsafe {
+	    mmap(NULL, total, libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+	} {
+	    MAP_FAILED => return Err(io::Error::last_os_error()),
+	    NULL => _panic_invalid_address(),
+	    ptr => MappedSlice(unsafe {
+		UniqueSlice {
+		    mem: NonNull::new_unchecked(ptr as *mut u8),
+		    end: match NonNull::new((ptr as *mut u8).add(total)) {
+			Some(n) => n,
+			_ => _panic_invalid_address(),
+		    },
+		}
+	    }, 0),
+	};
+
+	let flags = flags.get_mmap_flags() | libc::MAP_FIXED;
+	let mut offset = 0;
+	for &(fd, len) in segments {
+	    if len == 0 {
+		continue;
+	    }
+	    // Mapped over `root`, which will unmap it when dropped on failure.
+	    if unsafe { mmap(root.0.as_mut_ptr().add(offset) as *mut _, len, perm.get_prot(), flags, fd, 0) } == MAP_FAILED {
+		return Err(io::Error::last_os_error());
+	    }
+	    offset += len;
+	}
+	Ok(Self {
+	    file: Anonymous,
+	    map: root,
+	})
+    }
+}
+
+//TODO: Continue copying from `utf8encode` at the //TODO (cont.) line
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use file::memory::MemoryFile;
+
+    /// Create a private anonymous mapping of `len` bytes
+    fn anonymous(len: usize) -> MappedFile<Anonymous>
+    {
+	struct AnonymousFlags;
+	unsafe impl MapFlags for AnonymousFlags
+	{
+	    fn get_mmap_flags(&self) -> libc::c_int {
+		libc::MAP_PRIVATE | libc::MAP_ANONYMOUS
+	    }
+	}
+	MappedFile::new(Anonymous, len, Perm::ReadWrite, AnonymousFlags).expect("Failed to create anonymous mapping")
+    }
+
+    /// Run `f` in a forked child process, and assert that it returned `true`.
+    ///
+    /// The child exits with `_exit()` straight after `f`, without returning into the test harness, even if `f` panics.
+    /// Since the harness is multi-threaded, `f` should only do async-signal-safe work (e.g. touch memory and make syscalls, not take locks.)
+    #[track_caller]
+    pub(crate) fn in_child(f: impl FnOnce() -> bool)
+    {
+	match unsafe { libc::fork() } {
+	    -1 => panic!("fork() failed: {}", io::Error::last_os_error()),
+	    0 => {
+		let ok = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or(false);
+		unsafe { libc::_exit(if ok { 0 } else { 1 }) }
+	    },
+	    child => {
+		let mut status = 0;
+		assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+		assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0, "Check failed in child process (status {status})");
+	    },
+	}
+    }
+
+    #[test]
+    fn checksum_known_content()
+    {
+	use std::{
+	    hash::{Hasher, DefaultHasher},
+	    io::Write,
+	};
+	const CONTENT: &[u8] = b"123456789";
+	let mut file = MemoryFile::with_size(CONTENT.len()).unwrap();
+	file.write_all(CONTENT).unwrap();
+	let map = MappedFile::new(file, CONTENT.len(), Perm::Readonly, Flags::Shared).unwrap();
+
+	let mut expected = DefaultHasher::new();
+	expected.write(CONTENT);
+	let mut hasher = DefaultHasher::new();
+	map.checksum(&mut hasher);
+	assert_eq!(hasher.finish(), expected.finish());
+
+	#[cfg(feature="checksum")]
+	assert_eq!(map.crc32(), 0xCBF43926);
+    }
+
+    #[test]
+    fn hexdump_canonical()
+    {
+	let mut map = anonymous(get_page_size());
+	map[..12].copy_from_slice(b"Hello world\n");
+	let dump = map.hexdump().to_string();
+	let mut lines = dump.lines();
+	assert_eq!(lines.next(), Some("00000000  48 65 6c 6c 6f 20 77 6f  72 6c 64 0a 00 00 00 00  |Hello world.....|"));
+	assert_eq!(lines.next(), Some("00000010  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|"));
+	assert_eq!(lines.next(), Some("*"));
+	assert_eq!(lines.next(), Some(format!("{:08x}", get_page_size()).as_str()));
+	assert_eq!(lines.next(), None);
+
+	assert_eq!(Hexdump(b"Hello world\n").to_string(), "00000000  48 65 6c 6c 6f 20 77 6f  72 6c 64 0a              |Hello world.|\n0000000c\n");
+	assert!(!format!("{map:?}").contains("Hello"));
+    }
+
+    #[test]
+    fn debug_shows_metadata()
+    {
+	let map = MappedFile::anonymous_from(Anonymous, 16, Perm::Readonly).unwrap();
+	assert_eq!(format!("{map:?}"), format!("MappedFile {{ addr: {:#x}..{:#x}, len: 16, file: Anonymous }}", map.address(), map.address() + 16));
+    }
+
+    #[test]
+    fn get_set_bounds()
+    {
+	let page = get_page_size();
+	let mut map = anonymous(page);
+	map.set(page - 1, 0xaa).unwrap();
+	assert_eq!(map.get(page - 1), Some(0xaa));
+	assert_eq!(map.get(page), None);
+	assert_eq!(map.set(page, 0xaa), Err(OutOfBounds { index: page, len: page }));
+    }
+
+    #[test]
+    fn page_rounding()
+    {
+	let page = get_page_size();
+	assert_eq!(round_up_to_page(0), 0);
+	assert_eq!(round_up_to_page(1), page);
+	assert_eq!(round_up_to_page(page), page);
+	assert_eq!(round_up_to_page(page + 1), page * 2);
+	assert_eq!(round_down_to_page(0), 0);
+	assert_eq!(round_down_to_page(page - 1), 0);
+	assert_eq!(round_down_to_page(page), page);
+	assert_eq!(round_down_to_page(page * 2 - 1), page);
+    }
+
+    #[test]
+    fn concat_segments()
+    {
+	let page = get_page_size();
+	let files: Vec<_> = (0..3u8).map(|i| {
+	    let file = MemoryFile::with_size(page).unwrap();
+	    MappedFile::new(file.clone(), page, Perm::ReadWrite, Flags::Shared).unwrap().fill(i + 1);
+	    file
+	}).collect();
+	let map = MappedFile::concat(&[(files[0].as_raw_fd(), page), (files[1].as_raw_fd(), page), (files[2].as_raw_fd(), 1)], Perm::Readonly, Flags::Shared).unwrap();
+	drop(files);
+
+	assert_eq!(map.len(), page * 2 + 1);
+	assert!(map[..page].iter().all(|&b| b == 1));
+	assert!(map[page..(page * 2)].iter().all(|&b| b == 2));
+	assert_eq!(map[page * 2], 3);
+
+	assert_eq!(MappedFile::concat(&[(-1, 1), (-1, page)], Perm::Readonly, Flags::Shared).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(feature="strict-wxorx")]
+    fn concat_rejects_wx()
+    {
+	let page = get_page_size();
+	let file = MemoryFile::with_size(page).unwrap();
+	assert_eq!(MappedFile::concat(&[(file.as_raw_fd(), page)], Perm::WRX, Flags::Private).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn remap_to_fixed_address()
+    {
+	let page = get_page_size();
+	let mut map = anonymous(page);
+	map[0] = 0xaa;
+	let target = unsafe { libc::mmap(ptr::null_mut(), page, libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) };
+	assert_ne!(target, MAP_FAILED);
+	unsafe { map.remap_fixed(target) }.unwrap();
+	assert_eq!(map.as_slice().as_ptr(), target as *const u8);
+	assert_eq!(map[0], 0xaa);
+    }
+
+    #[test]
+    fn advice_set()
+    {
+	let set = Advice::Sequential | Advice::Normal;
+	assert!(!set.is_valid());
+	assert_eq!(set.iter().collect::<Vec<_>>(), [Advice::Normal, Advice::Sequential]);
+	assert!(AdviceSet::from(Advice::RandomAccess).is_valid());
+
+	let mut map = anonymous(get_page_size());
+	assert_eq!(map.advise(Advice::Sequential | Advice::RandomAccess, None).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+	map.advise(Advice::Sequential, Some(true)).unwrap();
+	map.advise(AdviceSet::empty(), None).unwrap();
+
+	assert!(!(Advice::DontDump | Advice::DoDump).is_valid());
+	map.advise(Advice::Sequential | Advice::DontDump, None).unwrap();
+	map.advise(Advice::DoDump, None).unwrap();
+    }
+
+    #[test]
+    fn wipe_on_fork()
+    {
+	const SENTINEL: u64 = 0xdead_beef_cafe_f00d;
+	let mut map = anonymous(get_page_size());
+	map.advise(Advice::WipeOnFork, None).unwrap();
+	unsafe { ptr::write_volatile(map.as_mut_ptr() as *mut u64, SENTINEL) };
+
+	// The child's copy is zeroed.
+	in_child(|| unsafe { ptr::read_volatile(map.as_ptr() as *const u64) } == 0);
+	// The parent's copy is untouched.
+	assert_eq!(unsafe { ptr::read_volatile(map.as_ptr() as *const u64) }, SENTINEL);
+
+	let mut shared = MappedFile::shared_anonymous(get_page_size(), Perm::ReadWrite).unwrap();
+	assert_eq!(shared.advise(Advice::WipeOnFork, None).unwrap_err().raw_os_error(), Some(libc::EINVAL));
     }
 
-    /// The size of the mapped memory
-    #[inline]
-    pub fn len(&self) -> usize
+    #[test]
+    fn mergeable_advice()
     {
-        self.map.0.len()
+	let mut map = anonymous(get_page_size() * 2);
+	// Kernels built without KSM reject the advice.
+	match map.advise(Advice::Mergeable, None) {
+	    Ok(()) => map.advise(Advice::Unmergeable, None).unwrap(),
+	    Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EINVAL)),
+	}
+	assert!(!(Advice::Mergeable | Advice::Unmergeable).is_valid());
+	assert!((Advice::Mergeable | Advice::DontFork | Advice::Sequential).is_valid());
     }
 
-    /// Get a slice of the mapped memory
-    #[inline]
-    pub fn as_slice(&self) -> &[u8]
+    #[test]
+    fn dont_fork()
     {
-        &self.map.0[..]
+	let page = get_page_size();
+	let mut map = MappedFile::shared_anonymous(page, Perm::ReadWrite).unwrap();
+	map.advise(Advice::DontFork, None).unwrap();
+	assert!(!(Advice::DontFork | Advice::DoFork).is_valid());
+
+	// The mapping is absent in the child.
+	in_child(|| unsafe {
+	    let mut vec = [0u8; 1];
+	    libc::mincore(map.as_ptr() as *mut _, page, vec.as_mut_ptr()) != 0 && *libc::__errno_location() == libc::ENOMEM
+	});
+	assert!(is_mapped(map.address(), page));
     }
 
-    /// Get a mutable slice of the mapped memory
-    #[inline]
-    pub fn as_slice_mut(&mut self) -> &mut [u8]
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn shared_anonymous_across_fork()
     {
-        &mut self.map.0[..]
+	use std::sync::atomic::{AtomicU64, Ordering};
+	let map = MappedFile::fork_shared(get_page_size()).unwrap();
+	let counter = unsafe { &*(map.as_ptr() as *const AtomicU64) };
+	counter.store(1, Ordering::SeqCst);
+	// The child sees the parent's write, and the parent sees the child's.
+	in_child(|| counter.fetch_add(10, Ordering::SeqCst) == 1);
+	assert_eq!(counter.load(Ordering::SeqCst), 11);
     }
 
-    /// Get a raw slice of the mapped memory
-    #[inline] 
-    pub fn as_raw_slice(&self) -> *const [u8]
+    #[test]
+    fn pagemap_residency()
     {
-	self.map.0.as_raw_slice()
+	let page = get_page_size();
+	let mut map = anonymous(page * 2);
+	map[0] = 1;
+	assert!(map.pagemap(0).unwrap().present);
+	assert!(!map.pagemap(1).unwrap().present);
+	assert_eq!(map.pagemap(2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
     }
 
-    /// Get a raw mutable slice of the mapped memory
-    #[inline]
-    pub fn as_raw_slice_mut(&mut self) -> *mut [u8]
+    #[test]
+    fn try_new_error_map_value()
     {
-	self.map.0.as_raw_slice_mut()
+	// A zero-length mapping is invalid.
+	let err = MappedFile::try_new(MemoryFile::new().unwrap(), 0, Perm::ReadWrite, Flags::Shared).map(drop).unwrap_err();
+	let err = err.map_value(|file| (file.as_raw_fd(), file));
+	assert_eq!(err.value().0, err.value().1.as_raw_fd());
+	assert_eq!(err.error().raw_os_error(), Some(libc::EINVAL));
     }
 
-    /// Checks if the mapping dangles (i.e. `len() == 0`.)
-    #[inline]
-    pub fn is_empty(&self) -> bool
+    #[test]
+    fn try_new_error_context()
     {
-        self.map.0.is_empty()
+	let err = MappedFile::try_new(MemoryFile::new().unwrap(), 0, Perm::ReadWrite, Flags::Shared).map(drop).unwrap_err();
+	let display = err.to_string();
+	let err = err.into_io_with_context();
+	assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+	assert_eq!(err.to_string(), display);
+	let message = err.to_string();
+	assert!(message.contains("MemoryFile") && message.contains(&io::Error::from_raw_os_error(libc::EINVAL).to_string()), "{message}");
     }
-}
 
-/// Error returned when mapping operation fails.
-///
-/// Also returns the value passed in.
-pub struct TryNewError<T: ?Sized>
-{
-    error: Box<io::Error>,
-    value: T
-}
+    #[test]
+    fn hugetlb_checked_unavailable()
+    {
+	use hugetlb::HugePageUnavailable;
+	// No system has 2kB huge-pages (3kB is rounded down to them.)
+	let err = MappedFile::try_new_hugetlb_checked(MemoryFile::new().unwrap(), 4096, Perm::ReadWrite, Flags::Private, HugePage::Dynamic { kilobytes: 3 }).map(drop).unwrap_err();
+	match hugetlb::cached_hugepage_sizes().as_ref() {
+	    Ok(_) => {
+		assert_eq!(err.error().kind(), io::ErrorKind::Unsupported);
+		let inner = err.error().get_ref().and_then(|e| e.downcast_ref::<HugePageUnavailable>()).expect("Error is not HugePageUnavailable");
+		assert_eq!(inner.requested_kb, 2);
+		assert!(err.error().to_string().starts_with("huge-page size 2kB is not available on this system (available: "));
+	    },
+	    // The sizes could not be scanned, so the scan's error is returned instead.
+	    Err(scan) => {
+		assert_eq!(err.error().kind(), scan.kind());
+		assert_eq!(err.error().to_string(), format!("Failed to determine available huge-page sizes: {scan}"));
+		assert!(err.error().get_ref().and_then(|e| e.downcast_ref::<HugePageUnavailable>()).is_none());
+	    },
+	}
+    }
 
-impl<T:?Sized> error::Error for TryNewError<T>
-{
-    #[inline] 
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-	Some(self.error.as_ref())
+    #[test]
+    fn copy_from_and_write_at()
+    {
+	let page = get_page_size();
+	let mut map = MappedFile::new(MemoryFile::with_size(page).unwrap(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	assert_eq!(map.copy_from(b"hello", true), Ok(5));
+	assert_eq!(&map[..5], b"hello");
+
+	let big = vec![0xaa; page + 10];
+	assert_eq!(map.copy_from(&big, true), Err(OutOfBounds { index: page, len: page }));
+	assert_eq!(&map[..5], b"hello");
+	assert_eq!(map.copy_from(&big, false), Ok(page));
+
+	assert_eq!(map.write_at(page - 3, b"world"), Ok(3));
+	assert_eq!(&map[page - 3..], b"wor");
+	assert_eq!(map.write_at(page, b"x"), Ok(0));
+	assert_eq!(map.write_at(page + 1, b"x"), Err(OutOfBounds { index: page + 1, len: page }));
     }
-}
 
-impl<T:?Sized> fmt::Display for TryNewError<T>
-{
-    #[inline] 
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    #[test]
+    fn read_at_across_end()
     {
-	write!(f, "error in mapping of type {}", std::any::type_name::<T>())
+	let page = get_page_size();
+	let mut map = MappedFile::new(MemoryFile::with_size(page).unwrap(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	map.write_at(page - 4, b"tail").unwrap();
+
+	let mut buf = [0u8; 8];
+	assert_eq!(map.read_at(page - 4, &mut buf), 4);
+	assert_eq!(&buf, b"tail\0\0\0\0");
+	assert_eq!(map.read_at(page - 8, &mut buf), 8);
+	assert_eq!(&buf, b"\0\0\0\0tail");
+	assert_eq!(map.read_at(page, &mut buf), 0);
+	assert_eq!(map.read_at(usize::MAX, &mut buf), 0);
     }
-}
 
-impl<T:?Sized> fmt::Debug for TryNewError<T>
-{
-    #[inline]
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    #[test]
+    fn anonymous_huge()
     {
-	f.debug_struct("TryNewError")
-	    .field("error", &self.error)
-	    .finish_non_exhaustive()
+	let mut map = MappedFile::anonymous_from(Anonymous, get_page_size(), Perm::ReadWrite).unwrap();
+	map[0] = 1;
+
+	let Some(&size_kb) = hugetlb::system_hugepage_sizes().ok().as_deref().and_then(<[usize]>::first) else {
+	    return;
+	};
+	let flag = MapHugeFlag::calculate_or_default(size_kb);
+	match MappedFile::anonymous_from(AnonymousHuge(flag), size_kb * 1024, Perm::ReadWrite) {
+	    Ok(mut map) => {
+		map[0] = 1;
+		map[size_kb * 1024 - 1] = 1;
+	    },
+	    // No huge-pages of this size are reserved.
+	    Err(e) => assert_eq!(e.raw_os_error(), Some(libc::ENOMEM)),
+	}
     }
-}
 
-impl<T: ?Sized> TryNewError<T>
-{
-    /// A reference to the value
-    #[inline] 
-    pub fn value(&self) -> &T
+    #[test]
+    fn evict_rereads_file()
     {
-	&self.value
+	use std::io::Write;
+	let page = get_page_size();
+	let mut file = MemoryFile::with_size(page * 2).unwrap();
+	file.write_all(b"on disk").unwrap();
+
+	let mut map = MappedFile::new(file, page * 2, Perm::ReadWrite, Flags::Private).unwrap();
+	map.prefetch(..).unwrap();
+	map[..7].copy_from_slice(b"private");
+	map[page] = 0xff;
+
+	// Only the first page is evicted: its private change is discarded and the file's contents are read back.
+	map.evict(..page).unwrap();
+	assert_eq!(&map[..7], b"on disk");
+	assert_eq!(map[page], 0xff);
     }
-    /// A mutable reference to the value
-    #[inline] 
-    pub fn value_mut(&mut self) -> &mut T
+
+    #[test]
+    fn flush_and_evict_keeps_shared_changes()
     {
-	&mut self.value
+	let page = get_page_size();
+	let file = MemoryFile::with_size(page).unwrap();
+	let mut map = MappedFile::new(file.clone(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	map[..5].copy_from_slice(b"saved");
+	map.flush_and_evict(.., Flush::Wait).unwrap();
+	assert_eq!(&map[..5], b"saved");
+
+	let other = MappedFile::new(file, page, Perm::Readonly, Flags::Shared).unwrap();
+	assert_eq!(&other[..5], b"saved");
     }
-    /// A reference to the IO error
-    #[inline] 
-    pub fn error(&self) -> &io::Error
+
+    #[test]
+    fn address_alignment()
     {
-	&self.error
+	let map = anonymous(get_page_size());
+	assert_eq!(map.address(), map.as_ptr() as usize);
+	assert!(map.alignment() >= get_page_size());
+	assert!(map.alignment().is_power_of_two());
+	assert!(map.address().is_multiple_of(map.alignment()));
+	assert!(!map.address().is_multiple_of(map.alignment() * 2));
     }
-    /// Consume a boxed instance and return the boxed IO error.
-    #[inline] 
-    pub fn into_error_box(self: Box<Self>) -> Box<io::Error>
+
+    #[test]
+    fn into_file_keeps_contents()
     {
-	self.error
+	use std::io::Read;
+	let mut map = MappedFile::new(MemoryFile::with_size(4).unwrap(), 4, Perm::ReadWrite, Flags::Shared).unwrap();
+	map.copy_from(b"file", true).unwrap();
+
+	let mut file = map.into_file();
+	let mut out = Vec::new();
+	file.read_to_end(&mut out).unwrap();
+	assert_eq!(out, b"file");
     }
-}
 
-impl<T> TryNewError<T>
-{
-    #[inline] 
-    fn wrap_last_error(value: T) -> Self
+    #[test]
+    fn try_from_file_maps_whole_file()
     {
-	Self {
-	    error: Box::new(io::Error::last_os_error()),
-	    value,
+	let file = std::fs::File::from(MemoryFile::with_size(get_page_size() + 1).unwrap());
+	let mut map = MappedFile::try_from(file).unwrap();
+	assert_eq!(map.len(), get_page_size() + 1);
+	map[get_page_size()] = 1;
+    }
+
+    #[test]
+    fn protect_readonly_file()
+    {
+	let file = MemoryFile::with_size(get_page_size()).unwrap();
+	let readonly = file.reopen(std::fs::OpenOptions::new().read(true)).unwrap();
+	let mut map = MappedFile::new(readonly, get_page_size(), Perm::Readonly, Flags::Shared).unwrap();
+	assert_eq!(map.protect(Perm::ReadWrite).unwrap_err().raw_os_error(), Some(libc::EACCES));
+
+	let mut map = MappedFile::new(file, get_page_size(), Perm::Readonly, Flags::Shared).unwrap();
+	map.protect(Perm::ReadWrite).unwrap();
+	map[0] = 1;
+	map.protect(Perm::Readonly).unwrap();
+	assert_eq!(map[0], 1);
+    }
+
+    /// If every page of `addr..addr+len` is mapped, according to `mincore()`.
+    fn is_mapped(addr: usize, len: usize) -> bool
+    {
+	let mut vec = vec![0u8; len.div_ceil(get_page_size())];
+	match unsafe { libc::mincore(addr as *mut _, len, vec.as_mut_ptr()) } {
+	    0 => true,
+	    _ => {
+		assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENOMEM));
+		false
+	    },
 	}
     }
 
-    #[inline] 
-    fn wrap((error, value): (impl Into<io::Error>, T)) -> Self
+    #[test]
+    fn dual_buffer_aliases_file()
     {
-	Self {
-	    error: Box::new(error.into()),
-	    value
+	use ring::buffer::Shared;
+	let page = get_page_size();
+	let file = MemoryFile::with_size(page).unwrap();
+	let mut direct = MappedFile::new(file.clone(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	let (mut tx, rx) = MappedFile::try_new_buffer::<Shared<_>>(file, page, ()).unwrap();
+
+	// One reservation: the reader directly follows the writer.
+	assert_eq!(rx.address(), tx.address() + page);
+	tx[..5].copy_from_slice(b"hello");
+	assert_eq!(&rx[..5], b"hello");
+	// Both alias offset 0 of the file.
+	assert_eq!(&direct[..5], b"hello");
+	direct[page - 1] = 0xee;
+	assert_eq!(rx[page - 1], 0xee);
+
+	// The ends are separate mappings, so each can be unmapped on its own.
+	let tx_entry = file::maps::find_mapping(tx.address()).unwrap().expect("writer not listed");
+	let rx_entry = file::maps::find_mapping(rx.address()).unwrap().expect("reader not listed");
+	assert!(tx_entry.perms.write && tx_entry.perms.shared && tx_entry.end == rx.address());
+	assert!(rx_entry.perms.read && !rx_entry.perms.write && rx_entry.start == rx.address());
+	drop(tx);
+	assert_eq!(&rx[..5], b"hello");
+    }
+
+    #[test]
+    fn dual_buffer_unsafe_writes()
+    {
+	use ring::buffer::Private;
+	let page = get_page_size();
+	let (tx, mut rx) = MappedFile::try_new_buffer_raw::<Private<_>>(MemoryFile::with_size(page).unwrap(), page, None, true, ()).unwrap();
+	rx[..4].copy_from_slice(b"back");
+	assert_eq!(&tx[..4], b"back");
+    }
+
+    #[test]
+    fn touch_pages_resident()
+    {
+	let page = get_page_size();
+	let file = MemoryFile::with_size(page * 4).unwrap();
+	let map = MappedFile::new(file.clone(), page * 4, Perm::Readonly, Flags::Shared).unwrap();
+	map.touch_pages();
+	assert!((0..4).all(|i| map.pagemap(i).unwrap().present));
+
+	let mut map = MappedFile::new(file, page * 4, Perm::ReadWrite, Flags::Private).unwrap();
+	map.touch_pages_mut();
+	// Written private pages are no longer the file's.
+	assert!((0..4).all(|i| { let e = map.pagemap(i).unwrap(); e.present && !e.file_or_shared }));
+	assert!(map.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn as_str_validates()
+    {
+	let mut map = MappedFile::new(MemoryFile::with_size(8).unwrap(), 8, Perm::ReadWrite, Flags::Shared).unwrap();
+	map.copy_from("héllo!\n".as_bytes(), true).unwrap();
+	assert_eq!(map.as_str(), Ok("héllo!\n"));
+	assert_eq!(unsafe { map.as_str_unchecked() }.lines().next(), Some("héllo!"));
+
+	// Cut the `é` in half.
+	map[2] = b'!';
+	let err = map.as_str().unwrap_err();
+	assert_eq!(err.valid_up_to(), 1);
+    }
+
+    #[test]
+    fn write_to_writer()
+    {
+	let page = get_page_size();
+	let mut map = anonymous(page * 3);
+	map.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+	let mut out = Vec::new();
+	assert_eq!(map.write_to(&mut out).unwrap(), page * 3);
+	assert_eq!(out, &map[..]);
+    }
+
+    #[test]
+    fn fill_from_reader()
+    {
+	use std::io::{Cursor, Read};
+	let page = get_page_size();
+	let mut map = anonymous(page);
+	map.as_slice_mut().fill(0xff);
+
+	// Shorter than the mapping.
+	let mut short = Cursor::new(vec![1u8; 100]);
+	assert_eq!(map.fill_from(&mut short).unwrap(), 100);
+	assert!(map[..100].iter().all(|&b| b == 1));
+	assert_eq!(map[100], 0xff);
+
+	// Longer than the mapping, in short reads: Only `len()` bytes are consumed.
+	let mut long = Cursor::new(vec![2u8; page + 10]);
+	struct Trickle<R>(R);
+	impl<R: Read> Read for Trickle<R>
+	{
+	    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+	    {
+		let n = buf.len().min(7);
+		self.0.read(&mut buf[..n])
+	    }
 	}
+	let mut trickle = Trickle(&mut long);
+	assert_eq!(map.fill_from(&mut trickle).unwrap(), page);
+	assert!(map.iter().all(|&b| b == 2));
+	let mut rest = Vec::new();
+	long.read_to_end(&mut rest).unwrap();
+	assert_eq!(rest.len(), 10);
     }
-    /// Consume into the contained value 
-    #[inline] 
-    pub fn into_inner(self) -> T
+
+    #[test]
+    fn interleave_all_nodes()
     {
-	self.value
+	assert_eq!(parse_node_list("0-3,5\n").unwrap(), [0, 1, 2, 3, 5]);
+	assert_eq!(parse_node_list("0").unwrap(), [0]);
+	assert!(parse_node_list("0-x").is_err());
+
+	let mut map = anonymous(get_page_size() * 4);
+	match map.interleave_all() {
+	    Ok(()) => map.touch_pages_mut(),
+	    // Kernels without NUMA support.
+	    Err(e) => assert!(matches!(e.raw_os_error(), Some(libc::ENOENT | libc::ENOSYS)), "{e}"),
+	}
     }
 
-    /// Consume into the IO error
-    #[inline] 
-    pub fn into_error(self) -> io::Error
+    #[test]
+    fn guarded_mapping()
     {
-	*self.error
+	let page = get_page_size();
+	let map = MappedFile::try_new_guarded(MemoryFile::with_size(page).unwrap(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	assert_eq!(map.len(), page);
+	assert!(map.iter().all(|&b| b == 0));
+
+	let addr = map.address();
+	for guard in [addr - page, addr + page] {
+	    let entry = file::maps::find_mapping(guard).unwrap().expect("guard page not mapped");
+	    assert_eq!(entry.perms, file::maps::MapPerms::default());
+	}
+
+	assert!(MappedFile::try_new_guarded(MemoryFile::new().unwrap(), 0, Perm::ReadWrite, Flags::Shared).is_err());
     }
-    /// Consume into the value and the error.
-    #[inline] 
-    pub fn into_parts(self) -> (T, io::Error)
+
+    #[test]
+    fn detach_backing_copies()
     {
-	(self.value, *self.error)
+	let page = get_page_size();
+	let file = MemoryFile::with_size(page).unwrap();
+	let mut map = MappedFile::new(file.clone(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	map[..4].copy_from_slice(b"file");
+	let addr = map.address();
+
+	map.detach_backing().unwrap();
+	assert_eq!(map.address(), addr);
+	assert_eq!(&map[..4], b"file");
+
+	map[..4].copy_from_slice(b"anon");
+	assert_eq!(&MappedFile::new(file.clone(), 4, Perm::Readonly, Flags::Shared).unwrap()[..], b"file");
+
+	// Accessing the mapping after the file is truncated would otherwise raise `SIGBUS`.
+	assert_eq!(unsafe { libc::ftruncate(file.as_raw_fd(), 0) }, 0);
+	assert_eq!(&map[..4], b"anon");
     }
-}
 
-impl<T: ?Sized> From<Box<TryNewError<T>>> for io::Error
-{
-    #[inline] 
-    fn from(from: Box<TryNewError<T>>) -> Self
+    #[test]
+    fn buffer_len_not_page_multiple()
     {
-	*from.error
+	use ring::buffer::Shared;
+	let page = get_page_size();
+	let err = MappedFile::try_new_buffer::<Shared<_>>(MemoryFile::with_size(page * 2).unwrap(), page + 1, ()).map(drop).unwrap_err();
+	assert_eq!(err.error().kind(), io::ErrorKind::InvalidInput);
+	assert!(err.error().to_string().contains(&page.to_string()), "{err}");
     }
-}
 
-impl<T> From<TryNewError<T>> for io::Error
-{
-    #[inline] 
-    fn from(from: TryNewError<T>) -> Self
+    #[test]
+    fn try_new_error_display()
     {
-	from.into_error()
+	let err = MappedFile::try_new(MemoryFile::new().unwrap(), 0, Perm::ReadWrite, Flags::Shared).map(drop).unwrap_err();
+	let message = err.to_string();
+	assert!(message.starts_with("error in mapping of type "), "{message}");
+	assert!(message.ends_with(&format!(": {}", io::Error::from_raw_os_error(libc::EINVAL))), "{message}");
     }
-}
 
-impl<T: AsRawFd> Borrow<T> for MappedFile<T>
-{
-    #[inline]
-    fn borrow(&self) -> &T
+    #[test]
+    fn rss_counts_faulted_pages()
     {
-        &self.file
+	let page = get_page_size();
+	let mut map = anonymous(page * 4);
+	let before = map.rss().unwrap();
+	map[0] = 1;
+	map[page * 2] = 1;
+	assert_eq!(map.rss().unwrap(), before + page * 2);
+	assert!(map.rss().unwrap() <= map.len());
     }
-}
 
-impl<T> Borrow<[u8]> for MappedFile<T>
-{
-    #[inline]
-    fn borrow(&self) -> &[u8]
+    #[test]
+    fn update_from_dirties_changed_pages()
     {
-        self.as_slice()
+	use std::io::Write;
+	let page = get_page_size();
+	let mut file = MemoryFile::new().unwrap();
+	file.write_all(&vec![7u8; page * 4]).unwrap();
+
+	// Written pages of a private file mapping are copied, and no longer reported as file pages.
+	let mut map = MappedFile::new(file, page * 4, Perm::ReadWrite, Flags::Private).unwrap();
+	let mut new = map.to_vec();
+	new[page * 2 + 10] = 8;
+	assert_eq!(map.update_from(&new), 1);
+	assert_eq!(&map[..], &new[..]);
+	for i in 0..4 {
+	    let entry = map.pagemap(i).unwrap();
+	    assert!(entry.present);
+	    assert_eq!(entry.file_or_shared, i != 2, "page {i}");
+	}
+	assert_eq!(map.update_from(&new), 0);
     }
-}
 
-impl<T> BorrowMut<[u8]> for MappedFile<T>
-{
-    #[inline]
-    fn borrow_mut(&mut self) -> &mut [u8]
+    #[test]
+    fn raw_parts_round_trip()
     {
-        self.as_slice_mut()
+	let page = get_page_size();
+	let mut map = MappedFile::new_guarded(MemoryFile::with_size(page).unwrap(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	map[0] = 42;
+	let (ptr, len, file) = map.into_raw_parts();
+	assert_eq!(len, page);
+	assert!(is_mapped(ptr as usize, len));
+
+	let map = unsafe { MappedFile::from_raw_parts(ptr, len, file) };
+	assert_eq!(map[0], 42);
     }
-}
 
-impl<T> AsRef<[u8]> for MappedFile<T>
-{
-    #[inline]
-    fn as_ref(&self) -> &[u8]
+    #[test]
+    fn empty_mapping_sync_and_advise()
     {
-	self.as_slice()
+	let file = std::fs::File::from(MemoryFile::new().unwrap());
+	let mut map = MappedFile::from_file(file, Perm::ReadWrite, Flags::Shared).unwrap();
+	assert!(map.is_empty());
+	map.flush(Flush::Wait).unwrap();
+	map.flush(Flush::Async).unwrap();
+	map.advise(Advice::Sequential, Some(true)).unwrap();
+	map.prefetch(..).unwrap();
+	map.evict(..).unwrap();
+	map.bind_numa(&[0], NumaPolicy::Bind).unwrap();
+	map.migrate_numa(&[0], NumaPolicy::Bind).unwrap();
+	// Only reading the node list can fail.
+	if let Err(e) = map.interleave_all() {
+	    assert_eq!(e.raw_os_error(), Some(libc::ENOENT), "{e}");
+	}
     }
-}
 
-impl<T> AsMut<[u8]> for MappedFile<T>
-{
-    #[inline]
-    fn as_mut(&mut self) -> &mut [u8]
+    #[test]
+    #[cfg(feature="digest")]
+    fn hash_into_matches_slice()
     {
-	self.as_slice_mut()
+	use sha2::{Digest, Sha256};
+	let page = get_page_size();
+	let mut map = anonymous(page * 3 + 17);
+	for (i, b) in map.iter_mut().enumerate() {
+	    *b = (i % 251) as u8;
+	}
+	let mut hasher = Sha256::new();
+	map.hash_into(&mut hasher);
+	assert_eq!(hasher.finalize(), Sha256::digest(map.as_slice()));
     }
-}
 
-impl<T> ops::Deref for MappedFile<T>
-{
-    type Target=  [u8];
-    #[inline]
-    fn deref(&self) -> &Self::Target
+    #[test]
+    fn bind_numa_node_zero()
     {
-        self.as_slice()
+	let page = get_page_size();
+	let mut map = anonymous(page * 4);
+	// Node 0 exists on every NUMA system, but the kernel may be built without NUMA support, or the policy may be refused.
+	match map.bind_numa(&[0], NumaPolicy::Bind) {
+	    Ok(()) => {
+		map.fill(1);
+		map.bind_numa(&[], NumaPolicy::Default).unwrap();
+		assert_eq!(map.bind_numa(&[0], NumaPolicy::Default).unwrap_err().raw_os_error(), Some(libc::EINVAL));
+	    },
+	    Err(e) => assert!(matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EPERM)), "{e}"),
+	}
     }
-}
-impl<T> ops::DerefMut for MappedFile<T>
-{
-    #[inline]
-    fn deref_mut(&mut self) -> &mut Self::Target
+
+    #[test]
+    fn mlock_all_and_unlock()
     {
-        self.as_slice_mut()
+	use file::MlockAllFlags;
+	// Locking affects the whole process, so do it in a child to leave the test harness (and other tests' locks) alone.
+	in_child(|| match file::mlock_all(MlockAllFlags::CURRENT | MlockAllFlags::ON_FAULT) {
+	    Ok(()) => file::munlock_all().is_ok(),
+	    // Unprivileged, over `RLIMIT_MEMLOCK`, or unsupported.
+	    Err(e) => matches!(e.raw_os_error(), Some(libc::EPERM | libc::ENOMEM | libc::ENOSYS)),
+	});
+	// Without `CURRENT` or `FUTURE`, the flags are invalid.
+	assert_eq!(file::mlock_all(MlockAllFlags::ON_FAULT).unwrap_err().raw_os_error(), Some(libc::EINVAL));
     }
-}
 
-/// Used for anonymous mappings with `MappedFile`.
-///
-/// # Safety
-/// The `AsRawFd` impl of this structure always returns `-1`. It should only be used with `MappedFile`, as this is an invlalid file descriptor in all other contexts.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
-pub struct Anonymous;
+    #[test]
+    fn padded_length()
+    {
+	let page = get_page_size();
+	let mut map = MappedFile::try_new_padded(MemoryFile::with_size(page).unwrap(), 1, Perm::ReadWrite, Flags::Shared).unwrap();
+	assert_eq!(map.len(), page);
+	map[page - 1] = 1;
+    }
 
-impl AsRawFd for Anonymous
-{
-    #[inline(always)] 
-    fn as_raw_fd(&self) -> RawFd {
-	-1
+    #[test]
+    fn chunks_over_pages()
+    {
+	let page = get_page_size();
+	let mut map = anonymous(page * 3);
+	for (i, page) in map.pages_mut().enumerate() {
+	    page.fill(i as u8);
+	}
+	assert_eq!(map.pages().count(), 3);
+	assert!(map.pages().enumerate().all(|(i, page)| page.iter().all(|&b| b == i as u8)));
+
+	let chunks: Vec<_> = map.chunks(page * 2).map(<[u8]>::len).collect();
+	assert_eq!(chunks, [page * 2, page]);
+	map.chunks_mut(page * 2).last().unwrap().fill(0xff);
+	assert!(map[(page * 2)..].iter().all(|&b| b == 0xff));
     }
-}
 
-//TODO: Continue copying from `utf8encode` at the //TODO (cont.) line
+    #[test]
+    fn slice_of_u64()
+    {
+	let page = get_page_size();
+	let mut map = MappedFile::new(MemoryFile::with_size(page).unwrap(), page, Perm::ReadWrite, Flags::Shared).unwrap();
+	let words = unsafe { map.as_slice_of_mut::<u64>() }.unwrap();
+	assert_eq!(words.len(), page / mem::size_of::<u64>());
+	words[1] = u64::MAX;
+	assert_eq!(&map[8..16], &[0xff; 8]);
+	assert_eq!(unsafe { map.as_slice_of::<u64>() }.unwrap()[1], u64::MAX);
+    }
+
+    #[test]
+    fn slice_of_overaligned()
+    {
+	#[repr(C, align(64))]
+	struct CacheLine([u8; 64]);
+
+	let page = get_page_size();
+	let map = MappedFile::new(MemoryFile::with_size(page).unwrap(), page, Perm::Readonly, Flags::Shared).unwrap();
+	let lines = unsafe { map.as_slice_of::<CacheLine>() }.unwrap();
+	assert_eq!(lines.len(), page / 64);
+	assert!(lines.iter().all(|CacheLine(line)| line.iter().all(|&b| b == 0)));
 
+	let err = unsafe { map.as_slice_of::<[u8; 3]>() }.unwrap_err();
+	assert!(err.size_mismatch);
+	assert_eq!(err.required, 1);
+    }
+}
@@ -4,3 +4,22 @@ use super::*;
 //TODO: Implement this w/ MAP_FIXED
 
 pub mod buffer;
+
+mod cursor;
+
+mod mirror;
+pub use mirror::RingBuffer;
+
+mod futex;
+pub use futex::{
+    blocking,
+    Producer,
+    Consumer,
+};
+
+mod channel;
+pub use channel::{
+    channel,
+    Sender,
+    Receiver,
+};
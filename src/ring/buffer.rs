@@ -176,42 +176,85 @@ impl<T: ?Sized> Private<T>
 
 pub trait BufferExt<T>
 {
+    /// Detach a mapped dual buffer 2-tuple into regular mapped inner types.
+    ///
+    /// # Panics
+    /// If duplicating either file descriptor fails. To handle this failure, use `detach_into()`.
     fn detach(txrx: Self) -> (MappedFile<T>, MappedFile<T>);
+
+    /// Detach a mapped dual buffer 2-tuple into regular mapped inner types.
+    ///
+    /// # Returns
+    /// If duplicating either file descriptor fails, then the error is returned and both mappings are unmapped.
+    fn detach_into(self) -> io::Result<(MappedFile<T>, MappedFile<T>)>;
 }
 
 impl<B, T> BufferExt<T> for (MappedFile<B>, MappedFile<B>)
 where B: TwoBufferProvider<T> + AsRawFd,
 T: FromRawFd,
 {
-    /// Detach a mapped dual buffer 2-tuple into regular mapped inner types.
     #[inline] 
-    fn detach((itx, irx): Self) -> (MappedFile<T>, MappedFile<T>) {
+    fn detach(txrx: Self) -> (MappedFile<T>, MappedFile<T>) {
 	#[cold]
 	#[inline(never)]
-	fn _panic_bad_dup(fd: RawFd) -> !
+	fn _panic_bad_dup(error: io::Error) -> !
 	{
-	    panic!("Failed to dup({fd}): {}", io::Error::last_os_error())
+	    panic!("Failed to dup() buffer file descriptor: {error}")
 	}
-	let tx = itx.file.as_raw_fd();
-	let rx = irx.file.as_raw_fd();
+	txrx.detach_into().unwrap_or_else(|e| _panic_bad_dup(e))
+    }
+
+    /// # Ownership
+    /// Each mapping's file descriptor is `dup()`'d, and each new `T` owns its duplicate, which refers to the same open file as the original.
+    /// The original `B` handles are then dropped: For `Shared`/`Private`, this releases both references to the shared file handle, which closes the original file descriptor.
+    /// The mapped memory itself is unaffected, and is now owned by the returned mappings.
+    fn detach_into(self) -> io::Result<(MappedFile<T>, MappedFile<T>)> {
+	let (itx, irx) = self;
+	// If the second `dup()` fails, the first is closed on drop.
+	let tx = file::ManagedFD::alias(&itx.file)?;
+	let rx = file::ManagedFD::alias(&irx.file)?;
 	
 	let (f0, f1) = unsafe {
-	    let fd1 = libc::dup(tx);
-	    if fd1 < 0 {
-		_panic_bad_dup(tx);
-	    }
-	    let fd2 = libc::dup(rx);
-	    if fd2 < 0 {
-		_panic_bad_dup(rx);
-	    }
-	    (T::from_raw_fd(fd1), T::from_raw_fd(fd2))
+	    (T::from_raw_fd(tx.into_raw_fd()), T::from_raw_fd(rx.into_raw_fd()))
 	};
-	(MappedFile {
+	Ok((MappedFile {
 	    map: itx.map,
 	    file: f0,
 	}, MappedFile {
 	    map: irx.map,
 	    file: f1
-	})
+	}))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::file::memory::MemoryFile;
+    use static_assertions::{
+	assert_impl_all,
+	assert_not_impl_any,
+    };
+
+    // `Private` holds an `Rc`: Neither half of a private dual buffer may leave the thread it was created on.
+    assert_not_impl_any!(Private<MemoryFile>: Send, Sync);
+    assert_not_impl_any!(MappedFile<Private<MemoryFile>>: Send, Sync);
+
+    // `Shared` holds an `Arc`: Both halves may be sent to, and shared between, threads when the file handle allows it.
+    assert_impl_all!(Shared<MemoryFile>: Send, Sync);
+    assert_impl_all!(MappedFile<Shared<MemoryFile>>: Send, Sync);
+
+    #[test]
+    fn anonymous_channel()
+    {
+	let page = get_page_size();
+	let (mut tx, rx) = MappedFile::try_new_buffer::<Shared<Anonymous>>(Anonymous, page, ()).unwrap();
+	assert_eq!(tx.inner().as_raw_fd(), -1);
+
+	tx[0] = 0xaa;
+	tx[page - 1] = 0xbb;
+	let reader = std::thread::spawn(move || (rx[0], rx[page - 1]));
+	assert_eq!(reader.join().unwrap(), (0xaa, 0xbb));
     }
 }
@@ -0,0 +1,262 @@
+//! A single-producer, single-consumer byte channel over a dual `(tx, rx)` buffer mapping. See `channel()`.
+use super::*;
+use buffer::Shared;
+use cursor::Positions;
+use std::sync::{
+    Arc,
+    atomic::{
+	AtomicBool,
+	AtomicUsize,
+	Ordering,
+    },
+};
+
+/// The positions shared by both ends of a channel: the write and read positions, in `0..2 * capacity`.
+#[derive(Debug, Default)]
+struct Cursors
+{
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Set when either end is dropped.
+    closed: AtomicBool,
+}
+
+/// The writing end of a channel, over the write-only half of the buffer. See `channel()`.
+#[derive(Debug)]
+pub struct Sender<T>
+{
+    map: MappedFile<Shared<T>>,
+    cursors: Arc<Cursors>,
+    pos: Positions,
+    /// Write position
+    head: usize,
+}
+
+/// The reading end of a channel, over the read-only half of the buffer. See `channel()`.
+#[derive(Debug)]
+pub struct Receiver<T>
+{
+    map: MappedFile<Shared<T>>,
+    cursors: Arc<Cursors>,
+    pos: Positions,
+    /// Read position
+    tail: usize,
+}
+
+/// Create a byte channel of `len` bytes over `file`, with a `Sender` writing through a write-only mapping and a `Receiver` reading through a read-only mapping of the same data.
+///
+/// Both ends are non-blocking: `Sender::write()` fails with `WouldBlock` when the buffer is full, and `Receiver::read()` does when it is empty. For a channel that blocks instead, see `blocking()`.
+/// Wrapping around the end of the buffer is handled by the channel, so any amount of data can be sent through it.
+///
+/// Each end unmaps its half when it is dropped, and `file` is dropped along with the second of them.
+///
+/// # Returns
+/// See `MappedFile::try_new_buffer()`. `len` must be a non-zero multiple of the page size, and `flags` must be shared (e.g. `()`.)
+pub fn channel<T: AsRawFd>(file: T, len: usize, flags: impl MapFlags) -> Result<(Sender<T>, Receiver<T>), TryNewError<T>>
+{
+    if len == 0 {
+	return Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, "Cannot create a channel of length 0"), file)));
+    }
+    let Some(pos) = Positions::new(len) else {
+	return Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, format!("Channel length {len} is too large")), file)));
+    };
+    let (tx, rx) = MappedFile::try_new_buffer::<Shared<T>>(file, len, flags)?;
+    let cursors = Arc::new(Cursors::default());
+    Ok((Sender {
+	map: tx,
+	cursors: cursors.clone(),
+	pos,
+	head: 0,
+    }, Receiver {
+	map: rx,
+	cursors,
+	pos,
+	tail: 0,
+    }))
+}
+
+impl<T> Sender<T>
+{
+    /// The number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize
+    {
+	self.pos.capacity()
+    }
+
+    /// The number of bytes that can currently be written before the buffer is full.
+    #[inline]
+    pub fn remaining(&self) -> usize
+    {
+	self.pos.free(self.head, self.cursors.read.load(Ordering::Acquire))
+    }
+
+    /// Check if the `Receiver` has not been dropped.
+    #[inline]
+    pub fn is_connected(&self) -> bool
+    {
+	!self.cursors.closed.load(Ordering::Acquire)
+    }
+}
+
+impl<T> io::Write for Sender<T>
+{
+    /// Write as much of `buf` as fits into the buffer.
+    ///
+    /// # Returns
+    /// If the buffer is full, an error of kind `WouldBlock` is returned. If the `Receiver` has been dropped, an error of kind `BrokenPipe` is returned.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+	if buf.is_empty() {
+	    return Ok(0);
+	}
+	if !self.is_connected() {
+	    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Channel receiver has been dropped"));
+	}
+	let n = buf.len().min(self.remaining());
+	if n == 0 {
+	    return Err(io::ErrorKind::WouldBlock.into());
+	}
+	let cap = self.capacity();
+	let start = self.pos.index(self.head);
+	let first = n.min(cap - start);
+	let base = self.map.raw_parts().0;
+	// SAFETY: The receiver never reads the free space being written here.
+	unsafe {
+	    ptr::copy_nonoverlapping(buf.as_ptr(), base.add(start), first);
+	    ptr::copy_nonoverlapping(buf.as_ptr().add(first), base, n - first);
+	}
+	self.head = self.pos.advance(self.head, n);
+	self.cursors.written.store(self.head, Ordering::Release);
+	Ok(n)
+    }
+
+    /// Written data is visible to the `Receiver` immediately, so this does nothing.
+    #[inline]
+    fn flush(&mut self) -> io::Result<()>
+    {
+	Ok(())
+    }
+}
+
+impl<T> Receiver<T>
+{
+    /// The number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize
+    {
+	self.pos.capacity()
+    }
+
+    /// The number of unread bytes currently in the buffer.
+    #[inline]
+    pub fn available(&self) -> usize
+    {
+	self.pos.used(self.cursors.written.load(Ordering::Acquire), self.tail)
+    }
+
+    /// Check if the `Sender` has not been dropped.
+    #[inline]
+    pub fn is_connected(&self) -> bool
+    {
+	!self.cursors.closed.load(Ordering::Acquire)
+    }
+}
+
+impl<T> io::Read for Receiver<T>
+{
+    /// Read as many bytes as fit into `buf` out of the buffer.
+    ///
+    /// # Returns
+    /// If the buffer is empty, an error of kind `WouldBlock` is returned, or `Ok(0)` once the `Sender` has been dropped.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+	if buf.is_empty() {
+	    return Ok(0);
+	}
+	// Checked first, so data written before the sender was dropped is still read.
+	let connected = self.is_connected();
+	let n = buf.len().min(self.available());
+	if n == 0 {
+	    return if connected { Err(io::ErrorKind::WouldBlock.into()) } else { Ok(0) };
+	}
+	let cap = self.capacity();
+	let start = self.pos.index(self.tail);
+	let first = n.min(cap - start);
+	let base = self.map.raw_parts().0;
+	// SAFETY: The sender never writes to the unread region being read here.
+	unsafe {
+	    ptr::copy_nonoverlapping(base.add(start), buf.as_mut_ptr(), first);
+	    ptr::copy_nonoverlapping(base, buf.as_mut_ptr().add(first), n - first);
+	}
+	self.tail = self.pos.advance(self.tail, n);
+	self.cursors.read.store(self.tail, Ordering::Release);
+	Ok(n)
+    }
+}
+
+impl<T> ops::Drop for Sender<T>
+{
+    #[inline]
+    fn drop(&mut self)
+    {
+	self.cursors.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T> ops::Drop for Receiver<T>
+{
+    #[inline]
+    fn drop(&mut self)
+    {
+	self.cursors.closed.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn send_wrapping_data()
+    {
+	let page = get_page_size();
+	let (mut tx, mut rx) = channel(Anonymous, page, ()).unwrap();
+	let data: Vec<u8> = (0..=255).cycle().take(page * 8 + 123).collect();
+
+	let receiver = std::thread::spawn(move || {
+	    let mut out = Vec::new();
+	    let mut buf = [0; 1000];
+	    loop {
+		match rx.read(&mut buf) {
+		    Ok(0) => break out,
+		    Ok(n) => out.extend_from_slice(&buf[..n]),
+		    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::yield_now(),
+		    Err(e) => panic!("{e}"),
+		}
+	    }
+	});
+
+	let mut sent = 0;
+	while sent < data.len() {
+	    match tx.write(&data[sent..(sent + 777).min(data.len())]) {
+		Ok(n) => sent += n,
+		Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::yield_now(),
+		Err(e) => panic!("{e}"),
+	    }
+	}
+	drop(tx);
+	assert_eq!(receiver.join().unwrap(), data);
+    }
+
+    #[test]
+    fn broken_pipe()
+    {
+	let (mut tx, rx) = channel(Anonymous, get_page_size(), ()).unwrap();
+	tx.write_all(b"hello").unwrap();
+	drop(rx);
+	assert_eq!(tx.write(b"world").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,110 @@
+//! Read and write positions for the single-producer, single-consumer ring-buffers in this module
+//!
+//! Positions are kept in `0..2 * capacity` instead of counting total bytes: Wrapping a total at `usize::MAX` (or `u32::MAX` for a futex word) would make `total % capacity` jump whenever the capacity is not a power of two, which is reachable quickly on 32-bit targets.
+//! Using twice the range of the buffer distinguishes a full buffer (the positions are `capacity` apart) from an empty one (the positions are equal.)
+
+/// The arithmetic for a pair of write and read positions over a ring of `capacity` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Positions
+{
+    capacity: usize,
+}
+
+impl Positions
+{
+    /// Positions for a ring of `capacity` bytes.
+    ///
+    /// # Returns
+    /// `None` if `2 * capacity` overflows.
+    #[inline]
+    pub const fn new(capacity: usize) -> Option<Self>
+    {
+	match capacity.checked_mul(2) {
+	    Some(_) => Some(Self { capacity }),
+	    None => None,
+	}
+    }
+
+    /// The number of bytes the ring can hold.
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize
+    {
+	self.capacity
+    }
+
+    /// The exclusive upper bound of a position. (i.e. `2 * capacity()`.)
+    #[inline(always)]
+    pub const fn limit(&self) -> usize
+    {
+	self.capacity * 2
+    }
+
+    /// The offset into the ring of `pos`.
+    #[inline(always)]
+    pub const fn index(&self, pos: usize) -> usize
+    {
+	debug_assert!(pos < self.limit());
+	if pos >= self.capacity { pos - self.capacity } else { pos }
+    }
+
+    /// Move `pos` forward by `n` bytes.
+    ///
+    /// `n` must not be larger than `capacity()`.
+    #[inline(always)]
+    pub const fn advance(&self, pos: usize, n: usize) -> usize
+    {
+	debug_assert!(pos < self.limit() && n <= self.capacity);
+	let left = self.limit() - pos;
+	if n >= left { n - left } else { pos + n }
+    }
+
+    /// The number of bytes between the read position `read` and the write position `written`. (i.e. the unread bytes.)
+    #[inline(always)]
+    pub const fn used(&self, written: usize, read: usize) -> usize
+    {
+	debug_assert!(written < self.limit() && read < self.limit());
+	if written >= read { written - read } else { self.limit() - read + written }
+    }
+
+    /// The number of bytes that can be written before the write position `written` catches up with the read position `read`.
+    #[inline(always)]
+    pub const fn free(&self, written: usize, read: usize) -> usize
+    {
+	self.capacity - self.used(written, read)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn wraps_at_twice_capacity()
+    {
+	// Not a power of two, so `total % capacity` would jump whenever a wrapping total wrapped.
+	let pos = Positions::new(3).unwrap();
+	let (mut written, mut read) = (0, 0);
+	let (mut total_written, mut total_read) = (0u64, 0u64);
+	for i in 0..100 {
+	    let n = (i % 4).min(pos.free(written, read));
+	    written = pos.advance(written, n);
+	    total_written += n as u64;
+
+	    let n = (i % 3).min(pos.used(written, read));
+	    read = pos.advance(read, n);
+	    total_read += n as u64;
+
+	    assert!(written < pos.limit() && read < pos.limit());
+	    assert_eq!(pos.index(written) as u64, total_written % 3);
+	    assert_eq!(pos.index(read) as u64, total_read % 3);
+	    assert_eq!(pos.used(written, read) as u64, total_written - total_read);
+	}
+
+	assert_eq!(pos.advance(5, 3), 2);
+	assert_eq!(pos.used(1, 4), 3);
+	assert_eq!(pos.free(1, 4), 0);
+	assert_eq!(pos.index(4), 1);
+	assert!(Positions::new(usize::MAX / 2 + 1).is_none());
+    }
+}
@@ -0,0 +1,262 @@
+//! Blocking single-producer, single-consumer ring-buffer, using `futex()` to sleep on an empty buffer
+//!
+//! The read and write positions live in a small `MAP_SHARED` control region, and are used directly as the futex words. Since the futex operations are not process-private, both ends keep working across `fork()`.
+//!
+//! # Memory ordering
+//! * The producer copies data into the buffer, then publishes the new write position with `Release` ordering. The consumer loads the write position with `Acquire` ordering before reading the data, so it always sees the data the position covers.
+//! * Likewise, the consumer publishes its read position with `Release` ordering only after copying the data out, and the producer loads it with `Acquire` ordering before it overwrites that space.
+//! * `FUTEX_WAIT` atomically checks the write position is still the one the consumer last saw before sleeping, so a wake-up between the consumer's check and its sleep is never lost.
+use super::*;
+use file::memory::MemoryFile;
+use cursor::Positions;
+use std::sync::{
+    Arc,
+    atomic::{
+	AtomicU32,
+	Ordering,
+    },
+};
+
+/// The shared control region of a blocking ring: the write and read positions, which always fit in 32 bits. (see `blocking()`.)
+#[derive(Debug)]
+struct Control(MappedFile<Anonymous>);
+
+impl Control
+{
+    const WRITTEN: usize = 0;
+    const READ: usize = 1;
+
+    #[inline]
+    fn new() -> io::Result<Self>
+    {
+	MappedFile::shared_anonymous(get_page_size(), Perm::ReadWrite).map(Self)
+    }
+
+    #[inline(always)]
+    fn word(&self, index: usize) -> &AtomicU32
+    {
+	// SAFETY: The mapping is page-aligned and at least a page long, and is only ever accessed atomically.
+	unsafe { &*(self.0.raw_parts().0 as *const AtomicU32).add(index) }
+    }
+
+    /// Block until the write position is no longer `read`.
+    fn wait_for_data(&self, read: u32) -> io::Result<()>
+    {
+	let word = self.word(Self::WRITTEN);
+	while word.load(Ordering::Acquire) == read {
+	    if unsafe { libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAIT, read, ptr::null::<libc::timespec>()) } != 0 {
+		match io::Error::last_os_error() {
+		    // The position changed before we slept, or we were interrupted: check again.
+		    e if matches!(e.raw_os_error(), Some(libc::EAGAIN | libc::EINTR)) => (),
+		    e => return Err(e),
+		}
+	    }
+	}
+	Ok(())
+    }
+
+    /// Wake a consumer blocked in `wait_for_data()`.
+    #[inline]
+    fn notify_data(&self) -> io::Result<()>
+    {
+	if unsafe { libc::syscall(libc::SYS_futex, self.word(Self::WRITTEN).as_ptr(), libc::FUTEX_WAKE, 1) } < 0 {
+	    Err(io::Error::last_os_error())
+	} else {
+	    Ok(())
+	}
+    }
+}
+
+#[derive(Debug)]
+struct Inner
+{
+    map: MappedFile<MemoryFile>,
+    pos: Positions,
+    control: Control,
+}
+
+/// The writing end of a blocking ring-buffer. See `blocking()`.
+#[derive(Debug)]
+pub struct Producer
+{
+    inner: Arc<Inner>,
+    /// Write position
+    head: usize,
+}
+
+/// The reading end of a blocking ring-buffer. See `blocking()`.
+#[derive(Debug)]
+pub struct Consumer
+{
+    inner: Arc<Inner>,
+    /// Read position
+    tail: usize,
+}
+
+/// Create a blocking single-producer, single-consumer ring-buffer of `capacity` bytes, backed by a new `MemoryFile`.
+///
+/// Reading from an empty buffer with `Consumer::pop()` blocks until the `Producer` pushes more data. The ends can be sent to other threads, or used across `fork()`.
+///
+/// # Returns
+/// If `capacity` is `0`, not a multiple of the page size (see `get_page_size()`), or twice it does not fit in 32 bits (the positions are kept in `0..2 * capacity`, and used as futex words), an error of kind `InvalidInput` is returned.
+/// If creating the memory file or either mapping fails, then the error is returned.
+pub fn blocking(capacity: usize) -> io::Result<(Producer, Consumer)>
+{
+    let pos = match Positions::new(capacity) {
+	Some(pos) if u32::try_from(pos.limit()).is_ok() => pos,
+	_ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Blocking ring-buffer capacity {capacity} is too large for 32-bit positions"))),
+    };
+    let map = mirror::map_mirrored(MemoryFile::with_size(capacity)?, capacity, ())?;
+    let inner = Arc::new(Inner {
+	map,
+	pos,
+	control: Control::new()?,
+    });
+    Ok((Producer {
+	inner: inner.clone(),
+	head: 0,
+    }, Consumer {
+	inner,
+	tail: 0,
+    }))
+}
+
+impl Producer
+{
+    /// The number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize
+    {
+	self.inner.pos.capacity()
+    }
+
+    /// The number of bytes that can currently be pushed before the buffer is full.
+    #[inline]
+    pub fn remaining(&self) -> usize
+    {
+	let read = self.inner.control.word(Control::READ).load(Ordering::Acquire);
+	self.inner.pos.free(self.head, read as usize)
+    }
+
+    /// Write as much of `data` as fits into the buffer without blocking, and wake the consumer if it is waiting for data.
+    ///
+    /// # Returns
+    /// The number of bytes written.
+    pub fn push(&mut self, data: &[u8]) -> io::Result<usize>
+    {
+	let n = data.len().min(self.remaining());
+	if n == 0 {
+	    return Ok(0);
+	}
+	let start = self.inner.pos.index(self.head);
+	// SAFETY: The consumer never reads the free space, and the mirrored mapping makes `start..start+n` contiguous.
+	unsafe {
+	    ptr::copy_nonoverlapping(data.as_ptr(), self.inner.map.raw_parts().0.add(start), n);
+	}
+	self.head = self.inner.pos.advance(self.head, n);
+	self.inner.control.word(Control::WRITTEN).store(self.head as u32, Ordering::Release);
+	self.notify_data()?;
+	Ok(n)
+    }
+
+    /// Wake the consumer if it is blocked waiting for data.
+    ///
+    /// `push()` does this automatically.
+    #[inline]
+    pub fn notify_data(&self) -> io::Result<()>
+    {
+	self.inner.control.notify_data()
+    }
+}
+
+impl Consumer
+{
+    /// The number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize
+    {
+	self.inner.pos.capacity()
+    }
+
+    /// The number of unread bytes currently in the buffer.
+    #[inline]
+    pub fn available(&self) -> usize
+    {
+	let written = self.inner.control.word(Control::WRITTEN).load(Ordering::Acquire);
+	self.inner.pos.used(written as usize, self.tail)
+    }
+
+    /// Block until there is data in the buffer to read.
+    #[inline]
+    pub fn wait_for_data(&self) -> io::Result<()>
+    {
+	self.inner.control.wait_for_data(self.tail as u32)
+    }
+
+    /// Read as many bytes as fit into `buf` out of the buffer, blocking until there is at least one if it is empty.
+    ///
+    /// # Returns
+    /// The number of bytes read, which is only 0 if `buf` is empty.
+    pub fn pop(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+	if buf.is_empty() {
+	    return Ok(0);
+	}
+	self.wait_for_data()?;
+	Ok(self.try_pop(buf))
+    }
+
+    /// Read as many bytes as fit into `buf` out of the buffer without blocking.
+    ///
+    /// # Returns
+    /// The number of bytes read.
+    pub fn try_pop(&mut self, buf: &mut [u8]) -> usize
+    {
+	let n = buf.len().min(self.available());
+	if n == 0 {
+	    return 0;
+	}
+	let start = self.inner.pos.index(self.tail);
+	// SAFETY: The producer never writes to the unread region, and the mirrored mapping makes `start..start+n` contiguous.
+	unsafe {
+	    ptr::copy_nonoverlapping(self.inner.map.raw_parts().0.add(start), buf.as_mut_ptr(), n);
+	}
+	self.tail = self.inner.pos.advance(self.tail, n);
+	self.inner.control.word(Control::READ).store(self.tail as u32, Ordering::Release);
+	n
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn pop_blocks_until_push()
+    {
+	let page = get_page_size();
+	let (mut tx, mut rx) = blocking(page).unwrap();
+	assert_eq!(rx.try_pop(&mut [0; 8]), 0);
+
+	let data: Vec<u8> = (0..=255).cycle().take(page * 8).collect();
+	let consumer = std::thread::spawn(move || {
+	    let mut out = Vec::new();
+	    let mut buf = [0; 1000];
+	    while out.len() < page * 8 {
+		let n = rx.pop(&mut buf).unwrap();
+		assert_ne!(n, 0);
+		out.extend_from_slice(&buf[..n]);
+	    }
+	    out
+	});
+
+	let mut sent = 0;
+	while sent < data.len() {
+	    sent += tx.push(&data[sent..(sent + 777).min(data.len())]).unwrap();
+	    std::thread::yield_now();
+	}
+	assert_eq!(consumer.join().unwrap(), data);
+	assert_eq!(tx.remaining(), page);
+    }
+}
@@ -0,0 +1,369 @@
+//! A byte ring-buffer over a mirrored mapping
+//!
+//! The backing file is mapped twice, back-to-back, so the region `len..2*len` mirrors `0..len`. Any `len`-byte window starting inside the first half is therefore contiguous in memory, even across the point where the buffer wraps.
+use super::*;
+use file::memory::MemoryFile;
+use cursor::Positions;
+
+/// Map the first `len` bytes of `file` twice, back-to-back, into one region of `2 * len` bytes.
+///
+/// The mapping is always `MAP_SHARED` (a private mapping would copy-on-write each half separately, breaking the mirror,) and readable and writable.
+///
+/// # Returns
+/// If `len` is `0` or not a multiple of the page size, an error of kind `InvalidInput` is returned alongside `file`.
+/// If any `mmap()` fails, then the error is returned alongside `file`.
+pub(crate) fn map_mirrored<T: AsRawFd>(file: T, len: usize, flags: impl MapFlags) -> Result<MappedFile<T>, TryNewError<T>>
+{
+    const NULL: *mut libc::c_void = ptr::null_mut();
+    let page = get_page_size();
+    if len == 0 || !len.is_multiple_of(page) {
+	return Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, format!("Mirrored mapping length {len} must be a non-zero multiple of the page size {page}")), file)));
+    }
+    let full_len = match len.checked_mul(2) {
+	Some(n) => n,
+	None => return Err(TryNewError::wrap((io::Error::new(io::ErrorKind::InvalidInput, format!("Mirrored mapping length {len} would overflow")), file))),
+    };
+
+    // Reserve the whole region, this mapping is what is unmapped on drop.
+    let mut root = match unsafe {
+	mmap(NULL, full_len, libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+    } {
+	MAP_FAILED => return Err(TryNewError::wrap_last_error(file)),
+	NULL => _panic_invalid_address(),
+	ptr => MappedSlice(unsafe {
+	    UniqueSlice {
+		mem: NonNull::new_unchecked(ptr as *mut u8),
+		end: match NonNull::new((ptr as *mut u8).add(full_len)) {
+		    Some(n) => n,
+		    _ => _panic_invalid_address(),
+		},
+	    }
+	}, 0),
+    };
+
+    let flags = (flags.get_mmap_flags() & !libc::MAP_PRIVATE) | libc::MAP_SHARED | libc::MAP_FIXED;
+    let prot = Perm::ReadWrite.get_prot();
+    let fd = file.as_raw_fd();
+    for half in [len, 0] {
+	// Mapped over `root`, which will unmap it when dropped on failure.
+	if unsafe { mmap(root.0.as_mut_ptr().add(half) as *mut _, len, prot, flags, fd, 0) } == MAP_FAILED {
+	    return Err(TryNewError::wrap_last_error(file));
+	}
+    }
+    Ok(MappedFile {
+	file,
+	map: root,
+    })
+}
+
+/// A single-producer, single-consumer byte ring-buffer over a mirrored mapping of file `T`.
+///
+/// Data written is read back in order. Because the mapping is mirrored, the written and unread regions are always contiguous in memory.
+#[derive(Debug)]
+pub struct RingBuffer<T = MemoryFile>
+{
+    map: MappedFile<T>,
+    pos: Positions,
+    /// Write position
+    head: usize,
+    /// Read position
+    tail: usize,
+}
+
+impl RingBuffer<MemoryFile>
+{
+    /// Create a new ring-buffer of `capacity` bytes, backed by a new `MemoryFile`.
+    ///
+    /// # Returns
+    /// If `capacity` is `0` or not a multiple of the page size (see `get_page_size()`), an error of kind `InvalidInput` is returned.
+    /// If creating the memory file or the mapping fails, then the error is returned.
+    pub fn new(capacity: usize) -> io::Result<Self>
+    {
+	Self::with_file(MemoryFile::with_size(capacity)?, capacity, ()).map_err(Into::into)
+    }
+}
+
+impl<T: AsRawFd> RingBuffer<T>
+{
+    /// Create a new ring-buffer of `capacity` bytes over the first `capacity` bytes of `file`.
+    ///
+    /// `file` must be at least `capacity` bytes long, and `capacity` must be a multiple of the page size (see `get_page_size()`).
+    /// The mapping is always shared, so `flags` should only be used to add flags (e.g. for huge-pages.)
+    ///
+    /// # Returns
+    /// If `capacity` is `0` or not a multiple of the page size, an error of kind `InvalidInput` is returned alongside `file`.
+    /// If mapping `file` fails, then the error is returned alongside `file`.
+    #[inline]
+    pub fn with_file(file: T, capacity: usize, flags: impl MapFlags) -> Result<Self, TryNewError<T>>
+    {
+	// `map_mirrored()` maps `2 * capacity` bytes, so it has already checked that it does not overflow.
+	map_mirrored(file, capacity, flags).map(|map| Self {
+	    map,
+	    pos: Positions::new(capacity).expect("Mirrored mapping length overflowed"),
+	    head: 0,
+	    tail: 0,
+	})
+    }
+}
+
+impl<T> RingBuffer<T>
+{
+    /// The number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize
+    {
+	self.pos.capacity()
+    }
+
+    /// The write position of the buffer, in `0..2 * capacity()`.
+    ///
+    /// Its distance ahead of `read()` is the number of unread bytes (see `available()`.)
+    #[inline]
+    pub fn written(&self) -> usize
+    {
+	self.head
+    }
+
+    /// The read position of the buffer, in `0..2 * capacity()`.
+    #[inline]
+    pub fn read(&self) -> usize
+    {
+	self.tail
+    }
+
+    /// The number of unread bytes in the buffer.
+    #[inline]
+    pub fn available(&self) -> usize
+    {
+	self.pos.used(self.head, self.tail)
+    }
+
+    /// The number of bytes that can be written before the buffer is full.
+    #[inline]
+    pub fn remaining(&self) -> usize
+    {
+	self.capacity() - self.available()
+    }
+
+    /// Check if there are no unread bytes in the buffer.
+    #[inline]
+    pub fn is_empty(&self) -> bool
+    {
+	self.available() == 0
+    }
+
+    /// Check if the buffer cannot hold any more bytes until some are read.
+    #[inline]
+    pub fn is_full(&self) -> bool
+    {
+	self.remaining() == 0
+    }
+
+    /// Get a contiguous slice of up to `n` bytes of free space in the buffer to write into.
+    ///
+    /// Because the mapping is mirrored, the slice is contiguous even if it crosses the point where the buffer wraps.
+    /// Bytes written into it are not readable until they are committed with `commit_write()`.
+    ///
+    /// # Returns
+    /// A slice of `min(n, remaining())` bytes.
+    #[inline]
+    pub fn contiguous_write_buf(&mut self, n: usize) -> &mut [u8]
+    {
+	let n = n.min(self.remaining());
+	let start = self.pos.index(self.head);
+	&mut self.map[start..(start + n)]
+    }
+
+    /// Mark the first `n` bytes of the free space (see `contiguous_write_buf()`) as written.
+    ///
+    /// # Panics
+    /// If `n` is larger than `remaining()`.
+    #[inline]
+    pub fn commit_write(&mut self, n: usize)
+    {
+	assert!(n <= self.remaining(), "Cannot commit {n} bytes to ring-buffer with {} bytes free", self.remaining());
+	self.head = self.pos.advance(self.head, n);
+    }
+
+    /// Write as much of `data` as fits into the buffer.
+    ///
+    /// # Returns
+    /// The number of bytes written.
+    pub fn push_slice(&mut self, data: &[u8]) -> usize
+    {
+	let buf = self.contiguous_write_buf(data.len());
+	let n = buf.len();
+	buf.copy_from_slice(&data[..n]);
+	self.commit_write(n);
+	n
+    }
+
+    /// Write as much of `data` as fits into the buffer, wrapping around its end.
+    ///
+    /// This is the same as `push_slice()`: The mirrored mapping makes the wrap-around a single copy.
+    ///
+    /// # Returns
+    /// The number of bytes written.
+    #[inline]
+    pub fn write_wrapping(&mut self, data: &[u8]) -> usize
+    {
+	self.push_slice(data)
+    }
+
+    /// Read as many unread bytes as fit into `buf` out of the buffer.
+    ///
+    /// # Returns
+    /// The number of bytes read.
+    pub fn pop_slice(&mut self, buf: &mut [u8]) -> usize
+    {
+	let n = buf.len().min(self.available());
+	buf[..n].copy_from_slice(&self.as_contiguous()[..n]);
+	self.consume(n);
+	n
+    }
+
+    /// All unread bytes in the buffer, in order, as a single slice.
+    ///
+    /// Because the mapping is mirrored, the slice is contiguous even if the unread bytes cross the point where the buffer wraps.
+    /// The bytes are not consumed, see `consume()`.
+    #[inline]
+    pub fn as_contiguous(&self) -> &[u8]
+    {
+	let start = self.pos.index(self.tail);
+	&self.map[start..(start + self.available())]
+    }
+
+    /// Look at all unread bytes in the buffer without consuming them.
+    ///
+    /// This is the same as `as_contiguous()`.
+    #[inline]
+    pub fn peek(&self) -> &[u8]
+    {
+	self.as_contiguous()
+    }
+
+    /// Mark the first `n` unread bytes (see `peek()`) as read.
+    ///
+    /// # Panics
+    /// If `n` is larger than `available()`.
+    #[inline]
+    pub fn consume(&mut self, n: usize)
+    {
+	assert!(n <= self.available(), "Cannot consume {n} bytes from ring-buffer with {} bytes available", self.available());
+	self.tail = self.pos.advance(self.tail, n);
+    }
+
+    /// A reference to the mapped file.
+    #[inline]
+    pub fn inner(&self) -> &T
+    {
+	self.map.inner()
+    }
+
+    /// Unmap the buffer and return the mapped file.
+    #[inline]
+    pub fn into_inner(self) -> T
+    {
+	self.map.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn occupancy()
+    {
+	let page = get_page_size();
+	let mut ring = RingBuffer::new(page).unwrap();
+	assert_eq!(ring.capacity(), page);
+	assert_eq!(ring.available(), 0);
+
+	assert_eq!(ring.push_slice(&[1; 100]), 100);
+	let mut buf = [0; 40];
+	assert_eq!(ring.pop_slice(&mut buf), 40);
+	assert_eq!(buf, [1; 40]);
+	assert_eq!(ring.written(), 100);
+	assert_eq!(ring.read(), 40);
+	assert_eq!(ring.available(), 60);
+	assert_eq!(ring.remaining(), page - 60);
+    }
+
+    #[test]
+    fn contiguous_write_across_wrap()
+    {
+	let page = get_page_size();
+	let mut ring = RingBuffer::new(page).unwrap();
+	ring.commit_write(page - 2);
+	let mut skip = vec![0; page - 2];
+	assert_eq!(ring.pop_slice(&mut skip), page - 2);
+
+	// The free space now starts 2 bytes before the end of the buffer.
+	let buf = ring.contiguous_write_buf(4);
+	assert_eq!(buf.len(), 4);
+	buf.copy_from_slice(b"abcd");
+	ring.commit_write(4);
+	assert_eq!(ring.write_wrapping(&vec![0xff; page]), page - 4);
+
+	let mut out = [0; 4];
+	assert_eq!(ring.pop_slice(&mut out), 4);
+	assert_eq!(&out, b"abcd");
+    }
+
+    #[test]
+    fn full_and_empty()
+    {
+	let page = get_page_size();
+	let mut ring = RingBuffer::new(page).unwrap();
+	assert!(ring.is_empty() && !ring.is_full());
+	assert!(ring.peek().is_empty());
+	assert_eq!(ring.pop_slice(&mut [0; 16]), 0);
+
+	assert_eq!(ring.push_slice(&vec![7; page + 10]), page);
+	assert!(ring.is_full() && !ring.is_empty());
+	assert_eq!(ring.push_slice(&[1]), 0);
+	assert!(ring.contiguous_write_buf(1).is_empty());
+	assert_eq!(ring.peek().len(), page);
+
+	ring.consume(page);
+	assert!(ring.is_empty());
+	assert_eq!(ring.written(), ring.read());
+    }
+
+    #[test]
+    fn peek_across_wrap()
+    {
+	let page = get_page_size();
+	let mut ring = RingBuffer::new(page).unwrap();
+	// Repeatedly push and pop odd-sized chunks, so the data wraps at every possible offset.
+	let data: Vec<u8> = (0..=255).cycle().take(page * 3).collect();
+	let mut out = Vec::new();
+	for chunk in data.chunks(page / 2 + 3) {
+	    assert_eq!(ring.push_slice(chunk), chunk.len());
+	    out.extend_from_slice(ring.as_contiguous());
+	    ring.consume(ring.available());
+	}
+	assert_eq!(out, data);
+	assert!(ring.written() < ring.capacity() * 2);
+
+	ring.commit_write(10);
+	ring.push_slice(b"tail");
+	assert_eq!(&ring.peek()[10..], b"tail");
+	assert_eq!(ring.peek(), ring.as_contiguous());
+    }
+
+    #[test]
+    fn mirrored_halves_alias()
+    {
+	let page = get_page_size();
+	let mut map = map_mirrored(MemoryFile::with_size(page).unwrap(), page, ()).unwrap();
+	map[page - 1] = 0xaa;
+	map[page] = 0xbb;
+	assert_eq!(map[page * 2 - 1], 0xaa);
+	assert_eq!(map[0], 0xbb);
+
+	assert_eq!(map_mirrored(MemoryFile::new().unwrap(), page + 1, ()).unwrap_err().error().kind(), io::ErrorKind::InvalidInput);
+    }
+}
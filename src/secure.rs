@@ -0,0 +1,125 @@
+//! Mappings that are scrubbed before they are unmapped. See `SecureMappedFile`.
+use super::*;
+
+/// A `MappedFile` whose memory is zeroed before it is unmapped, for buffers that hold secrets.
+///
+/// Created with `MappedFile::with_zero_on_drop()`. On drop, the whole mapping is overwritten with `explicit_bzero()`, which the compiler is not allowed to optimise away, and then unmapped as usual.
+/// Combine with `lock()` to also keep the pages out of swap while the secret is held.
+///
+/// # Limitations
+/// * Only the pages that are currently mapped are zeroed. Copies the kernel has already made elsewhere (pages written out to swap before they were locked, or the original pages of a private copy-on-write mapping) are not touched.
+/// * For a *shared* file-backed mapping, the zeroes are written through to the file. This is usually only what you want for a `MemoryFile` or an anonymous mapping.
+/// * If the mapping cannot be made writable on drop (e.g. a shared mapping of a file opened read-only), it is unmapped without being zeroed.
+/// * Copies of the data made out of the mapping (e.g. into a `Vec`) are not scrubbed.
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct SecureMappedFile<T: AsRawFd>(MappedFile<T>);
+
+impl<T: AsRawFd> MappedFile<T>
+{
+    /// Zero the mapped memory before it is unmapped. See `SecureMappedFile` for what this does and does not guarantee.
+    #[inline(always)]
+    pub fn with_zero_on_drop(self) -> SecureMappedFile<T>
+    {
+	SecureMappedFile(self)
+    }
+}
+
+impl<T: AsRawFd> SecureMappedFile<T>
+{
+    /// Lock the mapped pages into memory with `mlock()`, so the secret they hold is never written to swap.
+    ///
+    /// The pages are unlocked implicitly when the mapping is unmapped.
+    ///
+    /// # Returns
+    /// If `mlock()` fails (e.g. `ENOMEM` when `RLIMIT_MEMLOCK` would be exceeded.)
+    #[inline]
+    pub fn lock(&self) -> io::Result<()>
+    {
+	let (ptr, len) = self.0.raw_parts();
+	if len == 0 {
+	    return Ok(());
+	}
+	if unsafe { libc::mlock(ptr as *const _, len) } != 0 {
+	    Err(io::Error::last_os_error())
+	} else {
+	    Ok(())
+	}
+    }
+
+    /// The mapping, which is still zeroed on drop.
+    #[inline(always)]
+    pub fn inner(&self) -> &MappedFile<T>
+    {
+	&self.0
+    }
+
+    /// The mapping, which is still zeroed on drop.
+    #[inline(always)]
+    pub fn inner_mut(&mut self) -> &mut MappedFile<T>
+    {
+	&mut self.0
+    }
+}
+
+impl<T: AsRawFd> ops::Deref for SecureMappedFile<T>
+{
+    type Target = [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target
+    {
+	self.0.as_slice()
+    }
+}
+
+impl<T: AsRawFd> ops::DerefMut for SecureMappedFile<T>
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target
+    {
+	self.0.as_slice_mut()
+    }
+}
+
+impl<T: AsRawFd> ops::Drop for SecureMappedFile<T>
+{
+    fn drop(&mut self)
+    {
+	let (ptr, len) = self.0.raw_parts();
+	if len == 0 {
+	    return;
+	}
+	unsafe {
+	    // The mapping may have been created (or `protect()`ed) read-only; zeroing it then would fault.
+	    if libc::mprotect(ptr as *mut _, len, libc::PROT_READ | libc::PROT_WRITE) == 0 {
+		libc::explicit_bzero(ptr as *mut _, len);
+	    }
+	}
+	// `self.0` is unmapped when it is dropped after this.
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use file::memory::MemoryFile;
+    use std::io::Read;
+
+    #[test]
+    fn zeroed_before_unmap()
+    {
+	let page = get_page_size();
+	let file = MemoryFile::with_size(page).unwrap();
+	let mut alias = file.clone();
+
+	let mut map = MappedFile::new(file, page, Perm::ReadWrite, Flags::Shared).unwrap().with_zero_on_drop();
+	map.lock().unwrap_or_else(|e| assert_eq!(e.raw_os_error(), Some(libc::ENOMEM)));
+	map[..6].copy_from_slice(b"secret");
+	drop(map);
+
+	let mut buf = [0xff; 6];
+	alias.read_exact(&mut buf).unwrap();
+	assert_eq!(buf, [0; 6]);
+    }
+}
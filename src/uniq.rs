@@ -13,6 +13,11 @@ use std::{
 };
 
 /// A slice in which nothing is aliased. The `UniqueSlice<T>` *owns* all memory in between `mem` and `end`.
+///
+/// # Ownership
+/// A `UniqueSlice<T>` owns the *elements* in its range: When it is dropped, each one is dropped in place.
+/// It does not own the *memory* the range is in, and never frees or unmaps it. Whatever created the region (e.g. an `mmap()` call) is responsible for releasing it, after the `UniqueSlice<T>` is dropped.
+/// `MappedFile` uses it this way, unmapping the region after the slice is dropped.
 #[derive(Debug)]
 pub struct UniqueSlice<T> {
     pub(crate) mem: NonNull<T>, 
@@ -125,6 +130,47 @@ impl<T> ops::DerefMut for UniqueSlice<T>
 
 impl<T> UniqueSlice<T>
 {
+    /// Take ownership of the `len` elements starting at `mem`.
+    ///
+    /// # Safety
+    /// * `mem..mem+len` **must** be a valid, properly aligned range of initialised `T`s, which stays valid for the lifetime of the returned value.
+    /// * Nothing else may access the range while the returned value is alive: It is assumed to be unaliased.
+    /// * The elements are dropped when the returned value is dropped, so they **must not** be dropped by anything else. The memory itself is not freed (see the type's documentation.)
+    ///
+    /// # Panics
+    /// If `T` is zero-sized.
+    #[inline]
+    pub unsafe fn from_raw_parts(mem: NonNull<T>, len: usize) -> Self
+    {
+	assert!(mem::size_of::<T>() != 0, "UniqueSlice cannot hold zero-sized types");
+	Self {
+	    mem,
+	    end: NonNull::new_unchecked(mem.as_ptr().add(len)),
+	}
+    }
+
+    /// Split the owned range into `..mid` and `mid..`, transferring ownership of the elements of each to the respective half.
+    ///
+    /// # Note
+    /// The halves still lie in the same region of memory. Neither owns that region (see the type's documentation), so whatever releases it (e.g. with `munmap()`) must do so once, for the whole region, after both halves are dropped. Types building on this manage that teardown themselves.
+    ///
+    /// # Panics
+    /// If `mid > len()`.
+    pub fn split_at(self, mid: usize) -> (UniqueSlice<T>, UniqueSlice<T>)
+    {
+	let len = self.len();
+	assert!(mid <= len, "Split index {mid} out of bounds for UniqueSlice of length {len}");
+	let this = mem::ManuallyDrop::new(self);
+	let split = unsafe { NonNull::new_unchecked(this.mem.as_ptr().add(mid)) };
+	(UniqueSlice {
+	    mem: this.mem,
+	    end: split,
+	}, UniqueSlice {
+	    mem: split,
+	    end: this.end,
+	})
+    }
+
 #[inline(always)]
     pub fn is_empty(&self) -> bool
     {
@@ -175,7 +221,7 @@ impl<T> UniqueSlice<T>
     pub fn len(&self) -> usize
     {
 	unsafe {
-            (self.end.as_ptr().sub(self.mem.as_ptr() as usize) as usize) / mem::size_of::<T>()
+            self.end.as_ptr().offset_from(self.mem.as_ptr()) as usize
 	}
     }
 #[inline(always)]
@@ -223,3 +269,41 @@ impl<T> UniqueSlice<T>
     }
 }
 
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn split_at_halves()
+    {
+	let mut data = mem::ManuallyDrop::new([1u32, 2, 3, 4, 5]);
+	let slice = unsafe { UniqueSlice::from_raw_parts(NonNull::new(data.as_mut_ptr()).unwrap(), 5) };
+	let (left, right) = slice.split_at(2);
+	assert_eq!(&left[..], &[1, 2]);
+	assert_eq!(&right[..], &[3, 4, 5]);
+
+	let (empty, all) = right.split_at(0);
+	assert!(empty.is_empty());
+	assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_at_out_of_bounds()
+    {
+	let mut data = mem::ManuallyDrop::new([0u8; 4]);
+	let slice = unsafe { UniqueSlice::from_raw_parts(NonNull::new(data.as_mut_ptr()).unwrap(), 4) };
+	let _ = slice.split_at(5);
+    }
+
+    #[test]
+    fn from_raw_parts_len()
+    {
+	let mut data = mem::ManuallyDrop::new([1u32, 2, 3, 4]);
+	let slice = unsafe { UniqueSlice::from_raw_parts(NonNull::new(data.as_mut_ptr()).unwrap(), 4) };
+	assert_eq!(slice.len(), 4);
+	assert_eq!(slice.last(), Some(&4));
+	assert_eq!(&slice[..], &[1, 2, 3, 4]);
+    }
+}